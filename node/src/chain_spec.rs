@@ -1,6 +1,6 @@
 use iso_8583_runtime::{
-	AccountId, AuraConfig, BalancesConfig, GrandpaConfig, ISO8583Config, RuntimeGenesisConfig,
-	Signature, SudoConfig, SystemConfig, WASM_BINARY,
+	AccountId, AuraConfig, BalancesConfig, CouncilMembershipConfig, GrandpaConfig, ISO8583Config,
+	RuntimeGenesisConfig, Signature, SystemConfig, WASM_BINARY,
 };
 use sc_service::ChainType;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
@@ -57,8 +57,8 @@ pub fn development_config() -> Result<ChainSpec, String> {
 				wasm_binary,
 				// Initial PoA authorities
 				vec![authority_keys_from_seed("Alice")],
-				// Sudo account
-				get_account_id_from_seed::<sr25519::Public>("Alice"),
+				// Council members
+				vec![get_account_id_from_seed::<sr25519::Public>("Alice")],
 				// Pre-funded accounts
 				vec![
 					get_account_id_from_seed::<sr25519::Public>("Alice"),
@@ -97,8 +97,11 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 				wasm_binary,
 				// Initial PoA authorities
 				vec![authority_keys_from_seed("Alice"), authority_keys_from_seed("Bob")],
-				// Sudo account
-				get_account_id_from_seed::<sr25519::Public>("Alice"),
+				// Council members
+				vec![
+					get_account_id_from_seed::<sr25519::Public>("Alice"),
+					get_account_id_from_seed::<sr25519::Public>("Bob"),
+				],
 				// Pre-funded accounts
 				vec![
 					get_account_id_from_seed::<sr25519::Public>("Alice"),
@@ -127,7 +130,7 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 fn testnet_genesis(
 	wasm_binary: &[u8],
 	initial_authorities: Vec<(AuraId, GrandpaId)>,
-	root_key: AccountId,
+	council_members: Vec<AccountId>,
 	endowed_accounts: Vec<AccountId>,
 	_enable_println: bool,
 ) -> RuntimeGenesisConfig {
@@ -148,11 +151,13 @@ fn testnet_genesis(
 			authorities: initial_authorities.iter().map(|x| (x.1.clone(), 1)).collect(),
 			..Default::default()
 		},
-		sudo: SudoConfig {
-			// Assign network admin rights.
-			key: Some(root_key),
-		},
 		transaction_payment: Default::default(),
+		council: Default::default(),
+		council_membership: CouncilMembershipConfig {
+			// Council owns the oracle set and the payment processor configuration.
+			members: council_members.try_into().expect("council is not too large; qed"),
+			..Default::default()
+		},
 		iso8583: ISO8583Config {
 			oracle_accounts: vec![
 				get_account_id_from_seed::<sr25519::Public>("Alice"),
@@ -169,9 +174,9 @@ fn testnet_genesis(
 					.expect("valid account; qed"),
 			],
 			#[cfg(feature = "docker")]
-			payment_processor_url: b"http://server:3001".to_vec(),
+			processor_endpoints: vec![b"http://server:3001".to_vec()],
 			#[cfg(not(feature = "docker"))]
-			payment_processor_url: b"http://localhost:3001".to_vec(),
+			processor_endpoints: vec![b"http://localhost:3001".to_vec()],
 		},
 	}
 }
@@ -7,6 +7,13 @@ pub struct Cli {
 
 	#[clap(flatten)]
 	pub run: RunCmd,
+
+	/// Payment processor url queried by the offchain worker of this node.
+	///
+	/// Tried before the on-chain `ProcessorEndpoints`. It is kept in the offchain storage of the
+	/// node, so it stays in effect after a restart without the flag.
+	#[arg(long)]
+	pub payment_processor_url: Option<String>,
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -182,8 +182,9 @@ pub fn run() -> sc_cli::Result<()> {
 		},
 		None => {
 			let runner = cli.create_runner(&cli.run)?;
+			let payment_processor_url = cli.payment_processor_url.clone();
 			runner.run_node_until_exit(|config| async move {
-				service::new_full(config).map_err(sc_cli::Error::Service)
+				service::new_full(config, payment_processor_url).map_err(sc_cli::Error::Service)
 			})
 		},
 	}
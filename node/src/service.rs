@@ -10,6 +10,7 @@ use sc_service::{error::Error as ServiceError, Configuration, TaskManager, WarpS
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sc_transaction_pool_api::OffchainTransactionPoolFactory;
 use sp_consensus_aura::sr25519::AuthorityPair as AuraPair;
+use sp_core::offchain::OffchainStorage;
 use std::{sync::Arc, time::Duration};
 
 // Our native executor instance.
@@ -140,7 +141,13 @@ pub fn new_partial(
 }
 
 /// Builds a new service for a full client.
-pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
+///
+/// `payment_processor_url` is stored as the url of this node in offchain storage, where the
+/// offchain worker prefers it over the on-chain one.
+pub fn new_full(
+	config: Configuration,
+	payment_processor_url: Option<String>,
+) -> Result<TaskManager, ServiceError> {
 	let sc_service::PartialComponents {
 		client,
 		backend,
@@ -180,6 +187,17 @@ pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
 			warp_sync_params: Some(WarpSyncParams::WithProvider(warp_sync)),
 		})?;
 
+	if let Some(url) = payment_processor_url {
+		let mut offchain_storage = backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is not available".into()))?;
+		offchain_storage.set(
+			sp_core::offchain::STORAGE_PREFIX,
+			pallet_iso_8583::PAYMENT_PROCESSOR_URL_KEY,
+			url.as_bytes(),
+		);
+	}
+
 	if config.offchain_worker.enabled {
 		task_manager.spawn_handle().spawn(
 			"offchain-workers-runner",
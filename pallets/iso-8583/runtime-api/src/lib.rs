@@ -0,0 +1,32 @@
+//! Runtime API of the ISO-8583 pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Codec;
+use sp_std::vec::Vec;
+
+sp_api::decl_runtime_apis! {
+	/// Queries oracle health and performance of the ISO-8583 pallet.
+	pub trait ISO8583Api<AccountId, Liveness, Stats>
+	where
+		AccountId: Codec,
+		Liveness: Codec,
+		Stats: Codec,
+	{
+		/// Liveness of an oracle, `None` if it is not tracked.
+		fn oracle_health(oracle: AccountId) -> Option<Liveness>;
+
+		/// Liveness of all registered oracles.
+		fn oracles_health() -> Vec<(AccountId, Option<Liveness>)>;
+
+		/// Settlement statistics of an oracle, empty if it never attested.
+		fn oracle_stats(oracle: AccountId) -> Stats;
+
+		/// Settlement statistics of all oracles that attested at least once.
+		fn oracles_stats() -> Vec<(AccountId, Stats)>;
+
+		/// Payment processor endpoints in order of preference, flagged if the latest balance
+		/// update was served by them.
+		fn processor_endpoints() -> Vec<(Vec<u8>, bool)>;
+	}
+}
@@ -2,12 +2,14 @@
 
 use super::*;
 use crate::traits::ERC20R;
+use codec::DecodeAll;
 use frame_support::{
 	ensure,
 	pallet_prelude::DispatchResult,
 	traits::tokens::{currency::Currency, ExistenceRequirement},
 };
 
+use lite_json::JsonValue;
 use sp_runtime::{traits::TryConvert, SaturatedConversion};
 use sp_std::vec::Vec;
 
@@ -61,24 +63,74 @@ impl<T: Config> ERC20R<AccountIdOf<T>, BalanceOf<T>> for Pallet<T> {
 	}
 }
 
+/// Ensures origin is signed by an account registered in `OracleAccounts`.
+///
+/// Resolves to the oracle account.
+pub struct EnsureOracle<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Config> EnsureOrigin<T::RuntimeOrigin> for EnsureOracle<T> {
+	type Success = AccountIdOf<T>;
+
+	fn try_origin(o: T::RuntimeOrigin) -> Result<Self::Success, T::RuntimeOrigin> {
+		let origin: Result<frame_system::RawOrigin<AccountIdOf<T>>, T::RuntimeOrigin> = o.into();
+
+		origin.and_then(|o| match o {
+			frame_system::RawOrigin::Signed(who) if OracleAccounts::<T>::contains_key(&who) =>
+				Ok(who),
+			r => Err(T::RuntimeOrigin::from(r)),
+		})
+	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	fn try_successful_origin() -> Result<T::RuntimeOrigin, ()> {
+		let oracle = OracleAccounts::<T>::iter_keys().next().ok_or(())?;
+
+		Ok(frame_system::RawOrigin::Signed(oracle).into())
+	}
+}
+
 /// Converts `JsonValue` to `BalanceOf<T>`.
 pub(crate) struct BalanceDecoder<T: Config>(sp_std::marker::PhantomData<T>);
 
 impl<T: Config> TryConvert<&JsonValue, BalanceOf<T>> for BalanceDecoder<T> {
 	fn try_convert(json: &JsonValue) -> Result<BalanceOf<T>, &JsonValue> {
 		// Assumptions:
-		// - The number is a valid JSON number.
-		// - It is a `JsonValue::NumberValue` variant.
+		// - The balance is a non-negative JSON number, or a decimal string like `"100.11"`.
 		// - 6 decimals of the token.
-		json.clone()
-			.to_number()
-			.map(|num| {
+		match json {
+			JsonValue::Number(num) if !num.negative => {
 				let value_1 = num.integer as u128 * 10_u128.pow(num.exponent as u32 + 6);
 				let value_2 = num.fraction as u128 *
 					10_u128.pow(num.exponent as u32 + 6 - num.fraction_length);
-				(value_1 + value_2).saturated_into()
-			})
-			.ok_or(json)
+				Ok((value_1 + value_2).saturated_into())
+			},
+			JsonValue::String(chars) => {
+				let mut parts = chars.splitn(2, |c| *c == '.');
+				let integer = parts.next().unwrap_or_default();
+				let fraction = parts.next().unwrap_or_default();
+
+				if integer.is_empty() || fraction.len() > 6 {
+					return Err(json);
+				}
+
+				let digits = |digits: &[char]| {
+					digits.iter().try_fold(0_u128, |value, c| {
+						value.checked_mul(10)?.checked_add(c.to_digit(10)? as u128)
+					})
+				};
+
+				let integer = digits(integer).ok_or(json)?;
+				let fraction =
+					digits(fraction).ok_or(json)? * 10_u128.pow(6 - fraction.len() as u32);
+
+				integer
+					.checked_mul(1_000_000)
+					.and_then(|value| value.checked_add(fraction))
+					.map(|value| value.saturated_into())
+					.ok_or(json)
+			},
+			_ => Err(json),
+		}
 	}
 }
 
@@ -97,7 +149,7 @@ impl<T: Config> TryConvert<&JsonValue, AccountIdOf<T>> for AccountIdDecoder<T> {
 
 		let decoded_bytes = hex::decode(account_id_str).map_err(|_| json)?;
 
-		AccountIdOf::<T>::decode(&mut &decoded_bytes[..]).map_err(|_| json)
+		AccountIdOf::<T>::decode_all(&mut &decoded_bytes[..]).map_err(|_| json)
 	}
 }
 
@@ -134,6 +186,13 @@ mod tests {
 			.unwrap();
 
 			assert_eq!(balance, UNIT + UNIT / 2);
+
+			let balance = super::BalanceDecoder::<crate::mock::Test>::try_convert(
+				&JsonValue::String("100.11".chars().collect()),
+			)
+			.unwrap();
+
+			assert_eq!(balance, 100 * UNIT + 110_000);
 		});
 	}
 
@@ -0,0 +1,183 @@
+//! Sources of the offchain ledger balances.
+//!
+//! The offchain worker does not talk to the payment processor directly, it queries balances
+//! through the `LedgerSource` chosen in the pallet `Config`. This keeps the protocol of the
+//! processor out of the pallet. Initiated transfers are relayed to the processor through the
+//! `TransferRelay` of the `Config` likewise.
+
+use codec::Encode;
+use frame_system::{
+	offchain::{SignMessage, Signer},
+	pallet_prelude::BlockNumberFor,
+};
+use sp_core::RuntimeDebug;
+use sp_runtime::offchain::http;
+use sp_std::{marker::PhantomData, vec::Vec};
+
+use crate::{
+	types::{AccountIdOf, FinalisedTransactionOf, Hash, ProcessorSignature, TransferRequestOf},
+	AccountsOf, Config,
+};
+
+pub(crate) mod iso8583;
+pub(crate) mod json;
+
+pub use iso8583::Iso8583Ledger;
+pub use json::HttpJsonLedger;
+
+/// Error of a ledger source
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum LedgerError {
+	/// Payment processor URL is not valid UTF-8
+	InvalidUrl,
+	/// No local keys to sign the request with
+	NoSigner,
+	/// Request failed or did not finish before the deadline
+	Http(http::Error),
+	/// Response could not be parsed
+	InvalidResponse,
+	/// Payment processor answered with an HTTP status other than `200`
+	UnexpectedStatus(u16),
+	/// Response body is not valid UTF-8
+	InvalidEncoding,
+	/// Response body is not valid JSON
+	InvalidJson,
+	/// Response or one of its entries has an unexpected JSON type
+	UnexpectedShape,
+	/// Required field is missing from the response
+	MissingField(&'static str),
+	/// Account ID is not a hex encoded account
+	InvalidAccountId,
+	/// Balance is neither a non-negative number nor a decimal string
+	InvalidBalance,
+	/// Pagination cursor is not a string, or does not make progress
+	InvalidCursor,
+	/// Response holds more accounts than fit in a batch
+	TooManyAccounts,
+	/// Signature of the payment processor is malformed or does not match the response
+	InvalidSignature,
+	/// Source can not report changed balances, or relay transfers
+	Unsupported,
+}
+
+impl LedgerError {
+	/// Endpoint timed out or failed on its side, another endpoint can be tried
+	pub fn is_unavailable(&self) -> bool {
+		matches!(self, Self::Http(_) | Self::UnexpectedStatus(500..=599))
+	}
+}
+
+impl From<http::Error> for LedgerError {
+	fn from(error: http::Error) -> Self {
+		Self::Http(error)
+	}
+}
+
+/// Balances returned by a ledger source
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct LedgerBalances<Accounts> {
+	/// Balances of the requested accounts
+	pub accounts: Accounts,
+	/// Signature of the payment processor over the SCALE encoded `(accounts, at)`
+	pub signature: Option<ProcessorSignature>,
+}
+
+/// Explicit `LedgerBalances`
+pub type LedgerBalancesOf<T> = LedgerBalances<AccountsOf<T>>;
+
+/// Balances changed on the offchain ledger since a change cursor
+#[derive(Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct LedgerChanges<Accounts> {
+	/// Changed balances
+	pub balances: LedgerBalances<Accounts>,
+	/// Change cursor following the returned balances
+	pub cursor: Vec<u8>,
+}
+
+/// Explicit `LedgerChanges`
+pub type LedgerChangesOf<T> = LedgerChanges<AccountsOf<T>>;
+
+/// Source of balances of the offchain ledger
+pub trait LedgerSource<T: Config> {
+	/// Whether the source implements `fetch_changes`
+	const CHANGE_FEED: bool = false;
+
+	/// Fetch balances of `accounts` in block `at` from the processor endpoint at `url`
+	///
+	/// Accounts unknown to the ledger can be left out of the result. Sources that can not be
+	/// signed by the payment processor return no signature, and are rejected while
+	/// `PaymentProcessorKey` is set.
+	fn fetch_balances(
+		url: &[u8],
+		accounts: &[AccountIdOf<T>],
+		at: BlockNumberFor<T>,
+	) -> Result<LedgerBalancesOf<T>, LedgerError>;
+
+	/// Fetch balances changed since `cursor` in block `at` from the processor endpoint at `url`
+	///
+	/// Without a cursor the processor only returns its current cursor. At most `MaxBatchSize`
+	/// changes are returned, the returned cursor follows the last of them so the rest comes
+	/// with the next call. Changed accounts may not be registered on-chain.
+	fn fetch_changes(
+		_url: &[u8],
+		_cursor: Option<&[u8]>,
+		_at: BlockNumberFor<T>,
+	) -> Result<LedgerChangesOf<T>, LedgerError> {
+		Err(LedgerError::Unsupported)
+	}
+}
+
+/// Relay of transfers initiated on-chain to the payment processor
+pub trait TransferRelay<T: Config> {
+	/// Whether initiated transfers are queued for the offchain worker to relay them
+	const ENABLED: bool = true;
+
+	/// Send `requests`, keyed by their transaction ID, to the processor endpoint at `url` in
+	/// block `at`
+	///
+	/// Returns the outcome of the requests the processor answered, as transactions to attest.
+	/// Answers which can not be attested are left out. Requests left unanswered are sent again in
+	/// a later run, so the processor must recognise requests it already processed.
+	fn relay(
+		url: &[u8],
+		requests: &[(Hash, TransferRequestOf<T>)],
+		at: BlockNumberFor<T>,
+	) -> Result<Vec<FinalisedTransactionOf<T>>, LedgerError>;
+}
+
+/// Transfers are left to an external oracle
+impl<T: Config> TransferRelay<T> for () {
+	const ENABLED: bool = false;
+
+	fn relay(
+		_url: &[u8],
+		_requests: &[(Hash, TransferRequestOf<T>)],
+		_at: BlockNumberFor<T>,
+	) -> Result<Vec<FinalisedTransactionOf<T>>, LedgerError> {
+		Err(LedgerError::Unsupported)
+	}
+}
+
+/// Signer of the requests of the offchain worker to the payment processor
+pub trait RequestSigner<T: Config> {
+	/// SCALE encoded signature of `message`
+	fn sign(message: &[u8]) -> Result<Vec<u8>, LedgerError>;
+}
+
+/// Signs with the first `AuthorityId` key in the keystore of the node
+pub struct KeystoreSigner<T>(PhantomData<T>);
+
+impl<T: Config> RequestSigner<T> for KeystoreSigner<T> {
+	fn sign(message: &[u8]) -> Result<Vec<u8>, LedgerError> {
+		let signer = Signer::<T, T::AuthorityId>::all_accounts();
+		if !signer.can_sign() {
+			return Err(LedgerError::NoSigner);
+		}
+
+		signer
+			.sign_message(message)
+			.first()
+			.map(|(_, signature)| signature.encode())
+			.ok_or(LedgerError::NoSigner)
+	}
+}
@@ -0,0 +1,575 @@
+//! Balance inquiries with ISO-8583 0100 authorization requests, processing code 31, and relayed
+//! transfers.
+//!
+//! Every account of the batch is queried with its own 0100 message. Messages are ASCII encoded
+//! with a hex primary bitmap and sent together in a single POST to `{url}/iso8583`, each
+//! prefixed with its length as 2 big-endian bytes. The processor answers with 0110 messages in
+//! the same framing and reports balances in DE54.
+//!
+//! Request fields:
+//! - DE3, processing code `310000`
+//! - DE11, system trace audit number
+//! - DE48, hex encoded account ID
+//!
+//! Response fields read by the pallet: DE39 response code, DE48 account ID and DE54 additional
+//! amounts, of which the amount type of the `BalanceView` of the pallet is used. Accounts
+//! answered with a response code other than `00` are left out.
+//!
+//! Initiated transfers are relayed the same way, as 0200 financial requests with processing
+//! code `400000`, and reverts as 0400 reversal requests:
+//! - DE3, processing code `400000`
+//! - DE4, amount in minor units of the currency, transfers only
+//! - DE11, system trace audit number, the first 4 bytes of the transaction ID modulo 1000000
+//! - DE37, retrieval reference number, the first 6 bytes of the transaction ID in hex
+//! - DE48, hex encoded SCALE `(transaction ID, from, to)` of transfers, `(transaction ID, reverted
+//!   transaction ID)` of reverts
+//!
+//! The processor answers with 0210 and 0410 messages carrying the DE39 response code, the DE4
+//! amount and the hex encoded SCALE `(transaction ID, from, to)` of the settled funds in DE48.
+//! Only the response code is taken from the answer, the settled transaction is built from the
+//! request and answers whose DE4 or DE48 do not match it are rejected. The funds of a revert go
+//! back from the recipient to the sender of the reverted transfer, reverts of transfers which are
+//! not indexed anymore are not relayed.
+//!
+//! Every node relaying transfers sends the pending requests, and requests whose answer could not
+//! be attested are sent again. DE11 and DE37 only depend on the transaction ID, so the processor
+//! recognises a request it already processed.
+//!
+//! Responses are not signed, so this source can not be used while `PaymentProcessorKey` is set.
+
+use codec::{DecodeAll, Encode};
+use frame_support::traits::Get;
+use frame_system::pallet_prelude::BlockNumberFor;
+use sp_runtime::{offchain::http, SaturatedConversion};
+use sp_std::{collections::btree_map::BTreeMap, marker::PhantomData, vec, vec::Vec};
+
+use super::{LedgerBalances, LedgerBalancesOf, LedgerError, LedgerSource, TransferRelay};
+use crate::{
+	types::{
+		AccountIdOf, BalanceOf, EventId, FinalisedTransaction, FinalisedTransactionOf, Hash,
+		ISO8583FailureReason, ISO8583Status, LedgerView, RequestKind, TransferRequestOf,
+	},
+	AccountsOf, Config, Pallet,
+};
+
+/// Message type of an authorization request
+const REQUEST_MTI: &[u8; 4] = b"0100";
+/// Message type of an authorization request response
+pub(crate) const RESPONSE_MTI: &[u8; 4] = b"0110";
+/// Message type of a financial transaction request
+const TRANSFER_MTI: &[u8; 4] = b"0200";
+/// Message type of a financial transaction request response
+pub(crate) const TRANSFER_RESPONSE_MTI: &[u8; 4] = b"0210";
+/// Message type of a reversal request
+pub(crate) const REVERSAL_MTI: &[u8; 4] = b"0400";
+/// Message type of a reversal request response
+pub(crate) const REVERSAL_RESPONSE_MTI: &[u8; 4] = b"0410";
+/// Processing code of a balance inquiry from the default account, DE3
+const BALANCE_INQUIRY: &[u8; 6] = b"310000";
+/// Processing code of a transfer between default accounts, DE3
+const TRANSFER: &[u8; 6] = b"400000";
+/// Approved response code, DE39
+const APPROVED: &[u8; 2] = b"00";
+/// Ledger balance amount type of DE54
+const LEDGER_BALANCE: &[u8; 2] = b"01";
+/// Available balance amount type of DE54
+const AVAILABLE_BALANCE: &[u8; 2] = b"02";
+/// Number of decimals of DE54 amounts, which are in minor units of the currency
+const CURRENCY_EXPONENT: u32 = 2;
+/// Number of decimals of the token
+const TOKEN_DECIMALS: u32 = 6;
+/// Length of one additional amount of DE54
+const ADDITIONAL_AMOUNT_LENGTH: usize = 20;
+
+/// Queries balances with ISO-8583 balance inquiries
+pub struct Iso8583Ledger<T>(PhantomData<T>);
+
+impl<T: Config> LedgerSource<T> for Iso8583Ledger<T> {
+	fn fetch_balances(
+		url: &[u8],
+		accounts: &[AccountIdOf<T>],
+		at: BlockNumberFor<T>,
+	) -> Result<LedgerBalancesOf<T>, LedgerError> {
+		let stan_base = stan_base::<T>(at);
+
+		let requests = accounts
+			.iter()
+			.enumerate()
+			.map(|(index, account)| {
+				balance_inquiry(stan_base.wrapping_add(index as u32), account).encode()
+			})
+			.collect::<Vec<_>>();
+
+		let mut balances = Vec::new();
+
+		for message in exchange::<T>(url, &requests)? {
+			if message.mti != *RESPONSE_MTI {
+				return Err(LedgerError::InvalidResponse);
+			}
+
+			// account is unknown to the processor, or it can not answer now
+			if message.field(39) != Some(&APPROVED[..]) {
+				continue;
+			}
+
+			let account = message.field(48).ok_or(LedgerError::InvalidResponse)?;
+			let account = hex::decode(account).map_err(|_| LedgerError::InvalidResponse)?;
+			let account = AccountIdOf::<T>::decode_all(&mut &account[..])
+				.map_err(|_| LedgerError::InvalidResponse)?;
+
+			if !accounts.contains(&account) {
+				return Err(LedgerError::InvalidResponse);
+			}
+
+			let amounts = message.field(54).ok_or(LedgerError::InvalidResponse)?;
+			balances.push((account, balance::<T>(amounts)?));
+		}
+
+		let accounts: AccountsOf<T> =
+			balances.try_into().map_err(|_| LedgerError::TooManyAccounts)?;
+
+		Ok(LedgerBalances { accounts, signature: None })
+	}
+}
+
+impl<T: Config> TransferRelay<T> for Iso8583Ledger<T> {
+	fn relay(
+		url: &[u8],
+		requests: &[(Hash, TransferRequestOf<T>)],
+		_at: BlockNumberFor<T>,
+	) -> Result<Vec<FinalisedTransactionOf<T>>, LedgerError> {
+		let requests = requests
+			.iter()
+			.filter_map(|(hash, request)| {
+				let settlement = settlement::<T>(*hash, request);
+				if settlement.is_none() {
+					log::warn!(
+						target: "offchain-worker",
+						"Reverted transfer of {:?} is not indexed",
+						hash,
+					);
+				}
+
+				settlement.map(|settlement| (request, settlement))
+			})
+			.collect::<Vec<_>>();
+
+		if requests.is_empty() {
+			return Ok(Vec::new());
+		}
+
+		let messages = requests
+			.iter()
+			.map(|(request, settlement)| {
+				transfer_request::<T>(&settlement.hash, &request.kind).encode()
+			})
+			.collect::<Vec<_>>();
+		let settlements =
+			requests.into_iter().map(|(_, settlement)| settlement).collect::<Vec<_>>();
+
+		// a bad answer does not hold up the others, its request is sent again
+		let transactions = exchange::<T>(url, &messages)?
+			.iter()
+			.filter_map(|message| match transaction::<T>(message, &settlements) {
+				Ok(transaction) => Some(transaction),
+				Err(e) => {
+					log::warn!(
+						target: "offchain-worker",
+						"Invalid answer to a relayed request: {:?}",
+						e,
+					);
+					None
+				},
+			})
+			.collect();
+
+		Ok(transactions)
+	}
+}
+
+/// Funds moved by a relayed request, the response to it only adds the status
+pub(crate) struct Settlement<T: Config> {
+	/// Transaction ID
+	hash: Hash,
+	/// Sender
+	from: AccountIdOf<T>,
+	/// Receiver
+	to: AccountIdOf<T>,
+	/// Amount
+	amount: BalanceOf<T>,
+	/// Event ID of the request
+	event_id: EventId,
+}
+
+/// Funds moved by the request of transaction `hash`
+///
+/// Reverts move the funds of the reverted transfer back, `None` if it is not indexed anymore.
+pub(crate) fn settlement<T: Config>(
+	hash: Hash,
+	request: &TransferRequestOf<T>,
+) -> Option<Settlement<T>> {
+	let (from, to, amount) = match &request.kind {
+		RequestKind::Transfer { from, to, amount } => (from.clone(), to.clone(), *amount),
+		RequestKind::Revert { hash: reverted, .. } =>
+			match Pallet::<T>::transfer_request(reverted)?.kind {
+				RequestKind::Transfer { from, to, amount } => (to, from, amount),
+				RequestKind::Revert { .. } => return None,
+			},
+	};
+
+	Some(Settlement { hash, from, to, amount, event_id: request.event_id.clone() })
+}
+
+/// First system trace audit number of the balance inquiries of a run in block `at`
+///
+/// Unique within the interval, the offchain worker runs once per block at most and sends
+/// `MaxBatchSize` inquiries per run.
+fn stan_base<T: Config>(at: BlockNumberFor<T>) -> u32 {
+	at.saturated_into::<u32>().wrapping_mul(T::MaxBatchSize::get())
+}
+
+/// System trace audit number and retrieval reference number of the request of transaction `hash`
+///
+/// Requests sent again, by this node or another one, carry the same numbers.
+fn trace_numbers(hash: &Hash) -> (Vec<u8>, Vec<u8>) {
+	let bytes = hash.as_bytes();
+	let stan = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+	(format_number((stan % 1_000_000).into(), 6), hex::encode_upper(&bytes[..6]).into_bytes())
+}
+
+/// POST `messages` to `{url}/iso8583` and decode the messages of the response
+fn exchange<T: Config>(url: &[u8], messages: &[Vec<u8>]) -> Result<Vec<Message>, LedgerError> {
+	let deadline = sp_io::offchain::timestamp()
+		.add(sp_core::offchain::Duration::from_millis(T::OffchainRequestDeadline::get()));
+
+	let mut url = url.to_vec();
+	url.extend_from_slice(b"/iso8583");
+
+	let url_str = core::str::from_utf8(&url).map_err(|_| LedgerError::InvalidUrl)?;
+
+	let request = http::Request::new(url_str)
+		.method(http::Method::Post)
+		.deadline(deadline)
+		.body(vec![frame(messages)])
+		.add_header("Content-Type", "application/octet-stream")
+		.send()
+		.map_err(|_| http::Error::IoError)?;
+
+	let response = request.try_wait(deadline).map_err(|_| http::Error::DeadlineReached)??;
+
+	if response.code != 200 {
+		return Err(LedgerError::UnexpectedStatus(response.code));
+	}
+
+	let body = response.body().collect::<Vec<u8>>();
+
+	unframe(&body)?.into_iter().map(Message::decode).collect()
+}
+
+/// Balance inquiry of `account`
+pub(crate) fn balance_inquiry<AccountId: Encode>(stan: u32, account: &AccountId) -> Message {
+	let mut message = Message::new(*REQUEST_MTI);
+
+	message.set(3, BALANCE_INQUIRY.to_vec());
+	message.set(11, format_number((stan % 1_000_000).into(), 6));
+	message.set(48, hex::encode(account.encode()).into_bytes());
+
+	message
+}
+
+/// Financial request of a transfer, or reversal request of a revert
+pub(crate) fn transfer_request<T: Config>(
+	hash: &Hash,
+	kind: &RequestKind<AccountIdOf<T>, BalanceOf<T>>,
+) -> Message {
+	let (mut message, data) = match kind {
+		RequestKind::Transfer { from, to, amount } => {
+			let mut message = Message::new(*TRANSFER_MTI);
+			message.set(4, format_number(minor_units::<T>(*amount), 12));
+			(message, (hash, from, to).encode())
+		},
+		RequestKind::Revert { hash: reverted, .. } =>
+			(Message::new(*REVERSAL_MTI), (hash, reverted).encode()),
+	};
+
+	let (stan, rrn) = trace_numbers(hash);
+	message.set(3, TRANSFER.to_vec());
+	message.set(11, stan);
+	message.set(37, rrn);
+	message.set(48, hex::encode(data).into_bytes());
+
+	message
+}
+
+/// Transaction settled by the response to the request of one of `settlements`
+///
+/// Only the response code is taken from the response, its amount and funds must match the
+/// request.
+pub(crate) fn transaction<T: Config>(
+	message: &Message,
+	settlements: &[Settlement<T>],
+) -> Result<FinalisedTransactionOf<T>, LedgerError> {
+	if message.mti != *TRANSFER_RESPONSE_MTI && message.mti != *REVERSAL_RESPONSE_MTI {
+		return Err(LedgerError::InvalidResponse);
+	}
+
+	let data = message.field(48).ok_or(LedgerError::InvalidResponse)?;
+	let data = hex::decode(data).map_err(|_| LedgerError::InvalidResponse)?;
+	let (hash, from, to) = <(Hash, AccountIdOf<T>, AccountIdOf<T>)>::decode_all(&mut &data[..])
+		.map_err(|_| LedgerError::InvalidResponse)?;
+
+	let settlement = settlements
+		.iter()
+		.find(|settlement| settlement.hash == hash)
+		.ok_or(LedgerError::InvalidResponse)?;
+
+	let amount = parse_digits(message.field(4).ok_or(LedgerError::InvalidResponse)?)?;
+	if (&from, &to, amount) !=
+		(&settlement.from, &settlement.to, minor_units::<T>(settlement.amount))
+	{
+		return Err(LedgerError::InvalidResponse);
+	}
+
+	let code = message.field(39).ok_or(LedgerError::InvalidResponse)?;
+
+	Ok(FinalisedTransaction {
+		hash,
+		from,
+		to,
+		amount: settlement.amount,
+		event_id: settlement.event_id.clone(),
+		status: status(code),
+	})
+}
+
+/// Status of a transaction with the DE39 response `code`
+fn status(code: &[u8]) -> ISO8583Status {
+	match code {
+		b"00" => ISO8583Status::Approved,
+		b"05" => ISO8583Status::Failed(ISO8583FailureReason::DoNotHonor),
+		b"12" => ISO8583Status::Failed(ISO8583FailureReason::InvalidTransaction),
+		b"14" => ISO8583Status::Failed(ISO8583FailureReason::InvalidCardNumber),
+		b"51" => ISO8583Status::Failed(ISO8583FailureReason::InsufficientFunds),
+		b"54" => ISO8583Status::Failed(ISO8583FailureReason::ExpiredCard),
+		_ => ISO8583Status::Failed(ISO8583FailureReason::Other),
+	}
+}
+
+/// `value` as ASCII digits, left padded with zeros to `digits`
+fn format_number(mut value: u128, digits: usize) -> Vec<u8> {
+	let mut formatted = vec![b'0'; digits];
+
+	for digit in formatted.iter_mut().rev() {
+		*digit = b'0' + (value % 10) as u8;
+		value /= 10;
+	}
+
+	formatted
+}
+
+/// Balance in the `BalanceView` of the pallet from DE54 additional amounts
+///
+/// Debit balances are reported as zero.
+pub(crate) fn balance<T: Config>(amounts: &[u8]) -> Result<BalanceOf<T>, LedgerError> {
+	if amounts.is_empty() || amounts.len() % ADDITIONAL_AMOUNT_LENGTH != 0 {
+		return Err(LedgerError::InvalidResponse);
+	}
+
+	let view = match T::BalanceView::get() {
+		LedgerView::Available => AVAILABLE_BALANCE,
+		LedgerView::Ledger => LEDGER_BALANCE,
+	};
+	let mut minor_units = None;
+
+	for amount in amounts.chunks(ADDITIONAL_AMOUNT_LENGTH) {
+		// account type (2), amount type (2), currency code (3), sign (1), amount (12)
+		let amount_type = &amount[2..4];
+		let sign = amount[7];
+		let value = parse_digits(&amount[8..])?;
+
+		let value = match sign {
+			b'C' => value,
+			b'D' => 0,
+			_ => return Err(LedgerError::InvalidResponse),
+		};
+
+		if amount_type == view {
+			minor_units = Some(value);
+		}
+	}
+
+	let minor_units = minor_units.ok_or(LedgerError::InvalidResponse)?;
+
+	Ok(from_minor_units::<T>(minor_units))
+}
+
+/// Balance of an amount in minor units of the currency
+fn from_minor_units<T: Config>(minor_units: u128) -> BalanceOf<T> {
+	minor_units
+		.saturating_mul(10_u128.pow(TOKEN_DECIMALS - CURRENCY_EXPONENT))
+		.saturated_into()
+}
+
+/// Amount in minor units of the currency of a balance, fractions of a minor unit are dropped
+fn minor_units<T: Config>(balance: BalanceOf<T>) -> u128 {
+	balance.saturated_into::<u128>() / 10_u128.pow(TOKEN_DECIMALS - CURRENCY_EXPONENT)
+}
+
+/// Parse ASCII digits
+fn parse_digits(digits: &[u8]) -> Result<u128, LedgerError> {
+	digits.iter().try_fold(0_u128, |value, digit| {
+		if !digit.is_ascii_digit() {
+			return Err(LedgerError::InvalidResponse);
+		}
+
+		Ok(value.saturating_mul(10).saturating_add((digit - b'0') as u128))
+	})
+}
+
+/// Prefix every message with its length as 2 big-endian bytes
+pub(crate) fn frame(messages: &[Vec<u8>]) -> Vec<u8> {
+	let mut framed = Vec::new();
+
+	for message in messages {
+		framed.extend_from_slice(&(message.len() as u16).to_be_bytes());
+		framed.extend_from_slice(message);
+	}
+
+	framed
+}
+
+/// Split length prefixed messages
+pub(crate) fn unframe(mut framed: &[u8]) -> Result<Vec<&[u8]>, LedgerError> {
+	let mut messages = Vec::new();
+
+	while !framed.is_empty() {
+		if framed.len() < 2 {
+			return Err(LedgerError::InvalidResponse);
+		}
+
+		let length = u16::from_be_bytes([framed[0], framed[1]]) as usize;
+		let message = framed.get(2..2 + length).ok_or(LedgerError::InvalidResponse)?;
+
+		messages.push(message);
+		framed = &framed[2 + length..];
+	}
+
+	Ok(messages)
+}
+
+/// Length of an ISO-8583 data element
+enum FieldFormat {
+	/// Fixed number of characters
+	Fixed(usize),
+	/// Up to 99 characters, prefixed with 2 digits of length
+	LlVar,
+	/// Up to 999 characters, prefixed with 3 digits of length
+	LllVar,
+}
+
+/// Format of the data elements used by balance inquiries and transfers
+///
+/// Only elements of the primary bitmap are supported.
+fn field_format(field: u8) -> Option<FieldFormat> {
+	match field {
+		2 | 32 | 35 => Some(FieldFormat::LlVar),
+		3 | 11 | 12 | 38 => Some(FieldFormat::Fixed(6)),
+		4 | 37 => Some(FieldFormat::Fixed(12)),
+		7 => Some(FieldFormat::Fixed(10)),
+		39 => Some(FieldFormat::Fixed(2)),
+		41 => Some(FieldFormat::Fixed(8)),
+		42 => Some(FieldFormat::Fixed(15)),
+		49 => Some(FieldFormat::Fixed(3)),
+		48 | 54 => Some(FieldFormat::LllVar),
+		_ => None,
+	}
+}
+
+/// ASCII encoded ISO-8583 message with a primary bitmap
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Message {
+	/// Message type indicator
+	pub(crate) mti: [u8; 4],
+	/// Data elements by their number
+	fields: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Message {
+	pub(crate) fn new(mti: [u8; 4]) -> Self {
+		Self { mti, fields: BTreeMap::new() }
+	}
+
+	pub(crate) fn set(&mut self, field: u8, value: Vec<u8>) {
+		self.fields.insert(field, value);
+	}
+
+	pub(crate) fn field(&self, field: u8) -> Option<&[u8]> {
+		self.fields.get(&field).map(|value| &value[..])
+	}
+
+	pub(crate) fn encode(&self) -> Vec<u8> {
+		let mut bitmap = [0_u8; 8];
+		let mut data = Vec::new();
+
+		for (field, value) in &self.fields {
+			let index = (*field - 1) as usize;
+			bitmap[index / 8] |= 0x80 >> (index % 8);
+
+			match field_format(*field) {
+				Some(FieldFormat::LlVar) => data.extend(format_number(value.len() as u128, 2)),
+				Some(FieldFormat::LllVar) => data.extend(format_number(value.len() as u128, 3)),
+				_ => {},
+			}
+			data.extend_from_slice(value);
+		}
+
+		let mut message = self.mti.to_vec();
+		message.extend_from_slice(hex::encode_upper(bitmap).as_bytes());
+		message.extend(data);
+
+		message
+	}
+
+	pub(crate) fn decode(message: &[u8]) -> Result<Self, LedgerError> {
+		let mti = message.get(..4).ok_or(LedgerError::InvalidResponse)?;
+		let bitmap = message.get(4..20).ok_or(LedgerError::InvalidResponse)?;
+		let bitmap = hex::decode(bitmap).map_err(|_| LedgerError::InvalidResponse)?;
+
+		// secondary bitmap is not supported
+		if bitmap[0] & 0x80 != 0 {
+			return Err(LedgerError::InvalidResponse);
+		}
+
+		let mut decoded = Self::new([mti[0], mti[1], mti[2], mti[3]]);
+		let mut data = &message[20..];
+
+		for index in 1..64 {
+			if bitmap[index / 8] & (0x80 >> (index % 8)) == 0 {
+				continue;
+			}
+
+			let field = index as u8 + 1;
+			let length = match field_format(field).ok_or(LedgerError::InvalidResponse)? {
+				FieldFormat::Fixed(length) => length,
+				FieldFormat::LlVar => take_length(&mut data, 2)?,
+				FieldFormat::LllVar => take_length(&mut data, 3)?,
+			};
+
+			let value = data.get(..length).ok_or(LedgerError::InvalidResponse)?;
+			decoded.set(field, value.to_vec());
+			data = &data[length..];
+		}
+
+		Ok(decoded)
+	}
+}
+
+/// Read the length prefix of a variable length field
+fn take_length(data: &mut &[u8], digits: usize) -> Result<usize, LedgerError> {
+	let length = data.get(..digits).ok_or(LedgerError::InvalidResponse)?;
+	let length = parse_digits(length)? as usize;
+	*data = &data[digits..];
+
+	Ok(length)
+}
@@ -0,0 +1,324 @@
+//! Payment processor `/balances` JSON endpoints.
+
+use codec::{Decode, Encode};
+use frame_support::traits::Get;
+use frame_system::pallet_prelude::BlockNumberFor;
+use lite_json::{parse_json, JsonValue, NumberValue, Serialize};
+use sp_runtime::{
+	offchain::{http, Timestamp},
+	traits::TryConvert,
+	SaturatedConversion,
+};
+use sp_std::{marker::PhantomData, vec, vec::Vec};
+
+use super::{
+	KeystoreSigner, LedgerBalances, LedgerBalancesOf, LedgerChanges, LedgerChangesOf, LedgerError,
+	LedgerSource, RequestSigner,
+};
+use crate::{
+	impls::{AccountIdDecoder, BalanceDecoder},
+	types::{AccountIdOf, BalanceOf, ProcessorSignature},
+	Config,
+};
+
+/// Queries balances with a signed POST to `{url}/balances`
+///
+/// The processor answers with a list of balances, or with an object holding the list in
+/// `accounts` and its signature over the SCALE encoded `(accounts, at)` in `signature`. Balances
+/// are expected in the `BalanceView` of the pallet.
+///
+/// Responses can be split into pages: a page with a `next` cursor is followed by a request
+/// carrying it in `cursor`. Only the signature of the last page is kept, it covers the balances
+/// of all pages.
+///
+/// Changed balances are queried with a POST of `{"since": cursor, "limit": n}` to
+/// `{url}/balances/changes`, answered like a single page with the following change cursor in
+/// `cursor`.
+///
+/// Requests are signed by `S`, the keys of the node by default.
+pub struct HttpJsonLedger<T, S = KeystoreSigner<T>>(PhantomData<(T, S)>);
+
+impl<T: Config, S: RequestSigner<T>> LedgerSource<T> for HttpJsonLedger<T, S> {
+	const CHANGE_FEED: bool = true;
+
+	fn fetch_balances(
+		url: &[u8],
+		accounts: &[AccountIdOf<T>],
+		at: BlockNumberFor<T>,
+	) -> Result<LedgerBalancesOf<T>, LedgerError> {
+		let deadline = deadline::<T>();
+
+		// Body of the POST request, list of accounts
+		let body = JsonValue::Array(
+			accounts
+				.iter()
+				.map(|account| JsonValue::String(hex::encode(account.encode()).chars().collect()))
+				.collect::<Vec<_>>(),
+		);
+
+		// sign the body of the request
+		let signature = sign::<T, S>(&body.serialize())?;
+
+		let url = join(url, b"balances");
+		let at = number(at.saturated_into());
+
+		let mut balances = Vec::new();
+		let mut cursor: Option<Vec<char>> = None;
+
+		// follow the cursor until the last page
+		let signature = loop {
+			let mut fields = vec![
+				("accounts".chars().collect(), body.clone()),
+				("at".chars().collect(), at.clone()),
+				("signature".chars().collect(), signature.clone()),
+			];
+			if let Some(cursor) = &cursor {
+				fields.push(("cursor".chars().collect(), JsonValue::String(cursor.clone())));
+			}
+
+			let response = post(&url, JsonValue::Object(fields).serialize(), deadline)?;
+			let page = parse_page::<T>(&response)?;
+
+			if balances.len() + page.accounts.len() > T::MaxBatchSize::get() as usize {
+				return Err(LedgerError::TooManyAccounts);
+			}
+
+			let empty = page.accounts.is_empty();
+			balances.extend(page.accounts);
+
+			match page.next {
+				None => break page.signature,
+				// a page must make progress, otherwise the processor is looping
+				Some(next) if !empty && cursor.as_ref() != Some(&next) => cursor = Some(next),
+				Some(_) => return Err(LedgerError::InvalidCursor),
+			}
+		};
+
+		let accounts = balances.try_into().map_err(|_| LedgerError::TooManyAccounts)?;
+
+		Ok(LedgerBalances { accounts, signature })
+	}
+
+	fn fetch_changes(
+		url: &[u8],
+		cursor: Option<&[u8]>,
+		at: BlockNumberFor<T>,
+	) -> Result<LedgerChangesOf<T>, LedgerError> {
+		let deadline = deadline::<T>();
+
+		let since = match cursor {
+			Some(cursor) => JsonValue::String(
+				core::str::from_utf8(cursor)
+					.map_err(|_| LedgerError::InvalidCursor)?
+					.chars()
+					.collect(),
+			),
+			None => JsonValue::Null,
+		};
+
+		// sign the cursor the changes are requested from
+		let signature = sign::<T, S>(&since.serialize())?;
+
+		let url = join(url, b"balances/changes");
+
+		let body = JsonValue::Object(vec![
+			("since".chars().collect(), since),
+			("limit".chars().collect(), number(T::MaxBatchSize::get().into())),
+			("at".chars().collect(), number(at.saturated_into())),
+			("signature".chars().collect(), signature),
+		]);
+
+		let response = post(&url, body.serialize(), deadline)?;
+		let page = parse_page::<T>(&response)?;
+
+		let cursor = page.cursor.ok_or(LedgerError::MissingField("cursor"))?;
+		if page.accounts.len() > T::MaxBatchSize::get() as usize {
+			return Err(LedgerError::TooManyAccounts);
+		}
+		let accounts = page.accounts.try_into().map_err(|_| LedgerError::TooManyAccounts)?;
+
+		Ok(LedgerChanges {
+			balances: LedgerBalances { accounts, signature: page.signature },
+			cursor: utf8(&cursor),
+		})
+	}
+}
+
+/// Page of a `/balances` response
+pub(crate) struct Page<T: Config> {
+	/// Balances of the page
+	pub(crate) accounts: Vec<(AccountIdOf<T>, BalanceOf<T>)>,
+	/// Signature of the payment processor
+	pub(crate) signature: Option<ProcessorSignature>,
+	/// Cursor of the next page, `None` on the last page
+	pub(crate) next: Option<Vec<char>>,
+	/// Change cursor following the balances of a `/balances/changes` response
+	pub(crate) cursor: Option<Vec<char>>,
+}
+
+/// Deadline of requests started now
+fn deadline<T: Config>() -> Timestamp {
+	sp_io::offchain::timestamp()
+		.add(sp_core::offchain::Duration::from_millis(T::OffchainRequestDeadline::get()))
+}
+
+/// Signature of `message` by `S`, hex encoded
+fn sign<T: Config, S: RequestSigner<T>>(message: &[u8]) -> Result<JsonValue, LedgerError> {
+	let signature = S::sign(message)?;
+
+	Ok(JsonValue::String(hex::encode(signature).chars().collect()))
+}
+
+/// `{url}/{path}`
+fn join(url: &[u8], path: &[u8]) -> Vec<u8> {
+	let mut url = url.to_vec();
+	url.push(b'/');
+	url.extend_from_slice(path);
+	url
+}
+
+/// JSON integer
+fn number(integer: u64) -> JsonValue {
+	JsonValue::Number(NumberValue {
+		integer,
+		fraction: 0,
+		fraction_length: 0,
+		exponent: 0,
+		negative: false,
+	})
+}
+
+/// UTF-8 encoding of `chars`
+pub(crate) fn utf8(chars: &[char]) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(chars.len());
+	for c in chars {
+		bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+	}
+	bytes
+}
+
+/// POST `body` as JSON to `url`, returns the body of the response
+fn post(url: &[u8], body: Vec<u8>, deadline: Timestamp) -> Result<Vec<u8>, LedgerError> {
+	let url = core::str::from_utf8(url).map_err(|_| LedgerError::InvalidUrl)?;
+
+	// Form the request
+	let request = http::Request::new(url)
+		.method(http::Method::Post)
+		.deadline(deadline)
+		.body(vec![body])
+		.add_header("Content-Type", "application/json")
+		.add_header("accept", "*/*")
+		.send()
+		.map_err(|_| http::Error::IoError)?;
+
+	// Wait until the request is done, or until the deadline is reached
+	let response = request.try_wait(deadline).map_err(|_| http::Error::DeadlineReached)??;
+
+	if response.code != 200 {
+		return Err(LedgerError::UnexpectedStatus(response.code));
+	}
+
+	Ok(response.body().collect())
+}
+
+/// Parse a page of a `/balances` or `/balances/changes` response
+///
+/// Example response:
+/// ```json
+/// [
+///   {"accountId": "5GQ...", "balance": "100.11"},
+///   {"accountId": "5FQ...", "balance": 200.22},
+///   ..
+/// ]
+/// ```
+/// or, signed by the payment processor and paginated:
+/// ```json
+/// {"accounts": [..], "signature": "8a3f...", "next": "c2"}
+/// ```
+/// or, for changed balances:
+/// ```json
+/// {"accounts": [..], "signature": "8a3f...", "cursor": "1042"}
+/// ```
+///
+/// Fields are looked up by name, unknown fields are ignored.
+pub(crate) fn parse_page<T: Config>(body: &[u8]) -> Result<Page<T>, LedgerError> {
+	let body = core::str::from_utf8(body).map_err(|_| LedgerError::InvalidEncoding)?;
+
+	let (entries, signature, next, cursor) =
+		match parse_json(body).map_err(|_| LedgerError::InvalidJson)? {
+			JsonValue::Object(fields) => {
+				let entries =
+					field(&fields, &["accounts"]).ok_or(LedgerError::MissingField("accounts"))?;
+				let signature = field(&fields, &["signature"]).map(decode_signature).transpose()?;
+				let next = cursor_field(&fields, "next")?;
+				let cursor = cursor_field(&fields, "cursor")?;
+
+				(entries.clone(), signature, next, cursor)
+			},
+			entries => (entries, None, None, None),
+		};
+
+	let accounts = match entries {
+		JsonValue::Array(entries) =>
+			entries.iter().map(parse_balance::<T>).collect::<Result<Vec<_>, _>>()?,
+		_ => return Err(LedgerError::UnexpectedShape),
+	};
+
+	Ok(Page { accounts, signature, next, cursor })
+}
+
+/// Parse a `{"accountId": .., "balance": ..}` entry
+fn parse_balance<T: Config>(
+	entry: &JsonValue,
+) -> Result<(AccountIdOf<T>, BalanceOf<T>), LedgerError> {
+	let fields = match entry {
+		JsonValue::Object(fields) => fields,
+		_ => return Err(LedgerError::UnexpectedShape),
+	};
+
+	let account = field(fields, &["accountId", "account_id"])
+		.ok_or(LedgerError::MissingField("accountId"))?;
+	let balance = field(fields, &["balance"]).ok_or(LedgerError::MissingField("balance"))?;
+
+	let account =
+		AccountIdDecoder::<T>::try_convert(account).map_err(|_| LedgerError::InvalidAccountId)?;
+	let balance =
+		BalanceDecoder::<T>::try_convert(balance).map_err(|_| LedgerError::InvalidBalance)?;
+
+	Ok((account, balance))
+}
+
+/// Value of the first of `names` present in `fields`
+fn field<'a>(fields: &'a [(Vec<char>, JsonValue)], names: &[&str]) -> Option<&'a JsonValue> {
+	fields
+		.iter()
+		.find(|(key, _)| names.iter().any(|name| key.iter().copied().eq(name.chars())))
+		.map(|(_, value)| value)
+}
+
+/// Cursor in field `name`, a string or `null`
+fn cursor_field(
+	fields: &[(Vec<char>, JsonValue)],
+	name: &str,
+) -> Result<Option<Vec<char>>, LedgerError> {
+	match field(fields, &[name]) {
+		None | Some(JsonValue::Null) => Ok(None),
+		Some(JsonValue::String(cursor)) => Ok(Some(cursor.clone())),
+		Some(_) => Err(LedgerError::InvalidCursor),
+	}
+}
+
+/// Decode a hex encoded processor signature
+fn decode_signature(signature: &JsonValue) -> Result<ProcessorSignature, LedgerError> {
+	let signature = match signature {
+		JsonValue::String(signature) => signature.iter().map(|c| *c as u8).collect::<Vec<u8>>(),
+		_ => return Err(LedgerError::InvalidSignature),
+	};
+	let signature = hex::decode(signature).map_err(|_| LedgerError::InvalidSignature)?;
+
+	if signature.len() != 64 {
+		return Err(LedgerError::InvalidSignature);
+	}
+
+	ProcessorSignature::decode(&mut &signature[..]).map_err(|_| LedgerError::InvalidSignature)
+}
@@ -5,37 +5,49 @@
 /// messages to this pallet. This pallet will then perform the necessary actions to sync the
 /// offchain ledger with the onchain ledger.
 mod impls;
+pub mod ledger;
+pub mod migrations;
 mod traits;
 mod types;
 
 use frame_support::{
 	dispatch::Vec,
 	pallet_prelude::{ValueQuery, *},
-	traits::{BuildGenesisConfig, Currency, PalletInfoAccess, ReservableCurrency},
+	traits::{
+		BuildGenesisConfig, Currency, Imbalance, PalletInfoAccess, ReservableCurrency,
+		StorageVersion,
+	},
 };
 use frame_system::{
 	ensure_signed,
-	offchain::{ForAll, SendUnsignedTransaction, SignMessage, SignedPayload, Signer},
+	offchain::{SendSignedTransaction, SendUnsignedTransaction, SignedPayload, Signer},
 	pallet_prelude::OriginFor,
 };
 use sp_runtime::{
-	offchain::http,
-	traits::{TryConvert, Zero},
-	KeyTypeId, Saturating,
+	offchain::{
+		storage::StorageValueRef,
+		storage_lock::{StorageLock, Time},
+		Duration, StorageKind,
+	},
+	traits::{Hash as HashT, Zero},
+	KeyTypeId, SaturatedConversion, Saturating,
 };
 
 use frame_system::{offchain::CreateSignedTransaction, pallet_prelude::*};
-use lite_json::{parse_json, JsonValue, Serialize};
-use sp_std::vec;
 
+pub use impls::EnsureOracle;
+use ledger::{
+	LedgerBalances, LedgerBalancesOf, LedgerChanges, LedgerError, LedgerSource, TransferRelay,
+};
 pub use pallet::*;
 use traits::*;
 use types::*;
-
-#[cfg(test)]
-use crate::tests::MOCKED_SIGNATURE;
-
-use crate::impls::{AccountIdDecoder, BalanceDecoder};
+pub use types::{
+	FailoverState, FailoverStateOf, LedgerView, Liveness, LivenessOf, NetworkManagementCode,
+	NetworkManagementMessage, OracleStats, OracleStatsOf, OracleStatus, ProcessorKey,
+	ProcessorSignature, RequestKind, SettlementStats, StatsWindow, SubmissionMode, SyncState,
+	SyncStateOf, TransferRequest,
+};
 
 #[cfg(test)]
 mod mock;
@@ -51,8 +63,31 @@ mod tests;
 /// The keys can be inserted manually via RPC (see `author_insertKey`).
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"iso8");
 
-/// Max number of accounts to query in offchain worker
-pub const MAX_ACCOUNTS: u32 = 20;
+/// Offchain local storage key of the balance sync state
+pub const SYNC_STATE_KEY: &[u8] = b"iso8583::sync-state";
+
+/// Offchain local storage key of the payment processor url of this node
+///
+/// Holds the raw UTF-8 url, it is tried before `ProcessorEndpoints` if set and not empty.
+pub const PAYMENT_PROCESSOR_URL_KEY: &[u8] = b"iso8583::payment-processor-url";
+
+/// Offchain local storage key of the failover state of the payment processor endpoints
+pub const FAILOVER_STATE_KEY: &[u8] = b"iso8583::failover-state";
+
+/// Offchain local storage key of the lock held during an offchain worker run
+pub const SYNC_LOCK_KEY: &[u8] = b"iso8583::sync-lock";
+
+/// Offchain local storage key of the payment processor change cursor
+pub const CHANGE_CURSOR_KEY: &[u8] = b"iso8583::change-cursor";
+
+/// Offchain index prefix of transfer requests, followed by the transaction ID
+pub const TRANSFER_REQUEST_PREFIX: &[u8] = b"iso8583::request::";
+
+/// Offchain local storage key of the transaction IDs relayed by this node
+pub const RELAYED_REQUESTS_KEY: &[u8] = b"iso8583::relayed-requests";
+
+/// The current storage version.
+const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
 
 /// Based on the above `KeyTypeId` we need to generate a pallet-specific crypto type wrappers.
 /// We can use from supported crypto kinds (`sr25519`, `ed25519` and `ecdsa`) and augment
@@ -85,8 +120,12 @@ pub mod crypto {
 	}
 }
 
-/// List of accounts, bound is arbitrary, enough for our use case.
-type AccountsOf<T> = BoundedVec<(AccountIdOf<T>, BalanceOf<T>), ConstU32<30>>;
+/// Balances of a batch of accounts
+type AccountsOf<T> = BoundedVec<(AccountIdOf<T>, BalanceOf<T>), <T as Config>::MaxBatchSize>;
+
+/// Accounts queued for a balance sync, with the block they were queued in
+type DirtyAccountsOf<T> =
+	BoundedVec<(AccountIdOf<T>, BlockNumberFor<T>), <T as Config>::MaxDirtyAccounts>;
 
 /// Storage key as a bounded vector. The bound is arbitrary, enough for our use case.
 type StorageKey = BoundedVec<u8, ConstU32<128>>;
@@ -97,6 +136,7 @@ pub mod pallet {
 	use super::*;
 
 	#[pallet::pallet]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	pub struct Pallet<T>(_);
 
 	/// Pallet configuration
@@ -106,6 +146,12 @@ pub mod pallet {
 		type AuthorityId: frame_system::offchain::AppCrypto<Self::Public, Self::Signature>;
 		/// Because this pallet emits events, it depends on the runtime's definition of an event.
 		type RuntimeEvent: From<Event<Self>> + IsType<<Self as frame_system::Config>::RuntimeEvent>;
+		/// Source of the offchain ledger balances, queried by the offchain worker.
+		type LedgerSource: LedgerSource<Self>;
+		/// Relay of initiated transfers to the payment processor, run by the offchain worker
+		///
+		/// `()` leaves relaying to an external oracle.
+		type TransferRelay: TransferRelay<Self>;
 		/// Currency type to control the monetary system.
 		type Currency: ReservableCurrency<Self::AccountId>;
 		/// PalletAccount origin
@@ -117,12 +163,107 @@ pub mod pallet {
 		/// Interval between offchain worker runs
 		#[pallet::constant]
 		type OffchainWorkerInterval: Get<BlockNumberFor<Self>>;
+		/// Max number of accounts queried by an offchain worker run and updated by a balance
+		/// update
+		#[pallet::constant]
+		type MaxBatchSize: Get<u32>;
+		/// Interval between full scans of `Accounts`, a multiple of `OffchainWorkerInterval`
+		///
+		/// Runs in between only sync the balances changed since the previous run, if the ledger
+		/// source reports changes. Zero scans `Accounts` in every run.
+		#[pallet::constant]
+		type ReconciliationInterval: Get<BlockNumberFor<Self>>;
+		/// Max number of accounts queued for a balance sync before the scan of `Accounts`
+		#[pallet::constant]
+		type MaxDirtyAccounts: Get<u32>;
+		/// Number of blocks an account stays queued for a balance sync
+		///
+		/// Accounts the payment processor did not return a balance for in that time are left to
+		/// the scan of `Accounts`.
+		#[pallet::constant]
+		type DirtyAccountTimeout: Get<BlockNumberFor<Self>>;
+		/// Number of blocks an account waits between balance refresh requests
+		#[pallet::constant]
+		type RefreshCooldown: Get<BlockNumberFor<Self>>;
+		/// Whether accepted balance refresh requests are free of transaction fees
+		#[pallet::constant]
+		type FeelessRefresh: Get<bool>;
+		/// View of the offchain ledger that synced balances are in
+		///
+		/// Funds reserved by transfers in flight are taken out of `Ledger` balances, which still
+		/// include them.
+		#[pallet::constant]
+		type BalanceView: Get<LedgerView>;
+		/// Balance correction above which `DriftAlert` is emitted
+		#[pallet::constant]
+		type DriftAlertThreshold: Get<BalanceOf<Self>>;
+		/// How the offchain worker submits balance updates
+		///
+		/// Signed updates are sent from the oracle account of the offchain worker key, so oracle
+		/// nodes that do not produce blocks can submit them too.
+		#[pallet::constant]
+		type BalanceUpdateMode: Get<SubmissionMode>;
+		/// Deadline of the payment processor requests of an offchain worker run, in milliseconds
+		#[pallet::constant]
+		type OffchainRequestDeadline: Get<u64>;
+		/// Consecutive failed offchain worker runs before balance syncing is paused
+		///
+		/// Failed runs are retried with exponential backoff: 1, 2, 4, .. blocks later.
+		#[pallet::constant]
+		type MaxSyncRetries: Get<u32>;
+		/// Number of blocks balance syncing is paused for after `MaxSyncRetries` failed runs
+		#[pallet::constant]
+		type SyncPauseDuration: Get<BlockNumberFor<Self>>;
+		/// Max number of payment processor endpoints
+		#[pallet::constant]
+		type MaxEndpoints: Get<u32>;
+		/// Number of blocks between probes of the primary endpoint after a failover
+		#[pallet::constant]
+		type EndpointProbeInterval: Get<BlockNumberFor<Self>>;
+		/// Number of matching oracle attestations required to settle a transaction
+		#[pallet::constant]
+		type FinalityThreshold: Get<u32>;
+		/// Maximum number of oracles attesting a single transaction
+		#[pallet::constant]
+		type MaxOracles: Get<u32>;
+		/// Funds reserved from an oracle account when it is registered
+		#[pallet::constant]
+		type OracleBond: Get<BalanceOf<Self>>;
+		/// Number of blocks the bond of a removed oracle stays slashable before it can be withdrawn
+		#[pallet::constant]
+		type UnbondingPeriod: Get<BlockNumberFor<Self>>;
+		/// Number of blocks without a heartbeat after which an oracle is considered stale
+		#[pallet::constant]
+		type HeartbeatTimeout: Get<BlockNumberFor<Self>>;
+		/// Number of blocks after initiation a transfer should be attested in
+		#[pallet::constant]
+		type SettlementTimeout: Get<BlockNumberFor<Self>>;
+		/// Number of blocks settled transactions, and attestations and requests of transactions
+		/// that did not reach the quorum, are kept
+		///
+		/// Transfers initiated earlier can not be attested anymore.
+		#[pallet::constant]
+		type RetentionPeriod: Get<BlockNumberFor<Self>>;
+		/// Max number of expired transactions pruned in a block
+		#[pallet::constant]
+		type MaxPrunedPerBlock: Get<u32>;
+		/// Length of a window of oracle statistics, in blocks
+		#[pallet::constant]
+		type StatsWindowLength: Get<BlockNumberFor<Self>>;
+		/// Number of the most recent windows of oracle statistics that are kept
+		#[pallet::constant]
+		type MaxStatsWindows: Get<u32>;
+		/// Origin that manages oracles and the payment processor configuration
+		type AdminOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+		/// Origin of the oracle gateway, resolves to the oracle account
+		type OracleOrigin: EnsureOrigin<Self::RuntimeOrigin, Success = Self::AccountId>;
 	}
 
 	/// Accounts registered in the oracle
 	#[pallet::storage]
 	#[pallet::getter(fn accounts)]
-	pub type Accounts<T> = StorageMap<_, Blake2_128Concat, AccountIdOf<T>, ()>;
+	pub type Accounts<T: Config> =
+		StorageMap<_, Blake2_128Concat, AccountIdOf<T>, AccountInfoOf<T>>;
 
 	/// Allowances for accounts
 	///
@@ -142,7 +283,29 @@ pub mod pallet {
 	/// Registered oracle accounts
 	#[pallet::storage]
 	#[pallet::getter(fn oracle_accounts)]
-	pub type OracleAccounts<T> = StorageMap<_, Blake2_128Concat, AccountIdOf<T>, ()>;
+	pub type OracleAccounts<T: Config> =
+		StorageMap<_, Blake2_128Concat, AccountIdOf<T>, OracleInfoOf<T>>;
+
+	/// Bonds of registered and removed oracle accounts
+	///
+	/// Bond of a removed oracle is kept until it is withdrawn after `UnbondingPeriod`.
+	#[pallet::storage]
+	#[pallet::getter(fn oracle_bonds)]
+	pub type OracleBonds<T: Config> = StorageMap<_, Blake2_128Concat, AccountIdOf<T>, BondOf<T>>;
+
+	/// Liveness of registered oracles, updated by their heartbeats
+	#[pallet::storage]
+	#[pallet::getter(fn oracle_liveness)]
+	pub type OracleLiveness<T: Config> =
+		StorageMap<_, Blake2_128Concat, AccountIdOf<T>, LivenessOf<T>>;
+
+	/// Settlement statistics of oracles
+	///
+	/// Kept after an oracle is removed, so its record is known if it is registered again.
+	#[pallet::storage]
+	#[pallet::getter(fn oracle_stats)]
+	pub type OracleStatistics<T: Config> =
+		StorageMap<_, Blake2_128Concat, AccountIdOf<T>, OracleStatsOf<T>, ValueQuery>;
 
 	/// Last queried storage key for offchain worker
 	/// Offchain worker iterates through all the registered accounts, queries their balances
@@ -154,10 +317,98 @@ pub mod pallet {
 	#[pallet::getter(fn last_storage_key)]
 	pub type LastIteratedStorageKey<T: Config> = StorageValue<_, StorageKey, OptionQuery>;
 
-	/// Payment processor url
+	/// Accounts whose balances are synced in between the change syncs and scans of `Accounts`
+	///
+	/// `(Account, Block number it was queued in)`, queued by transfers, settlements and refresh
+	/// requests, in order. Accounts leave the queue once a balance update includes them or after
+	/// `DirtyAccountTimeout` blocks, accounts queued while it is full are left to the scan.
+	#[pallet::storage]
+	#[pallet::getter(fn dirty_accounts)]
+	pub type DirtyAccounts<T: Config> = StorageValue<_, DirtyAccountsOf<T>, ValueQuery>;
+
+	/// Cumulative corrections of on-chain balances by balance updates
+	#[pallet::storage]
+	#[pallet::getter(fn balance_drift)]
+	pub type BalanceDrift<T: Config> = StorageValue<_, DriftStatsOf<T>, ValueQuery>;
+
+	/// Latest balance refresh request of accounts
+	#[pallet::storage]
+	#[pallet::getter(fn refresh_requests)]
+	pub type RefreshRequests<T: Config> =
+		StorageMap<_, Blake2_128Concat, AccountIdOf<T>, RefreshRequestOf<T>>;
+
+	/// Payment processor endpoints, in order of preference
+	///
+	/// The offchain worker queries the first endpoint that is available, and fails back to the
+	/// primary once it recovers.
+	#[pallet::storage]
+	#[pallet::getter(fn processor_endpoints)]
+	pub type ProcessorEndpoints<T: Config> =
+		StorageValue<_, BoundedVec<StorageKey, T::MaxEndpoints>, ValueQuery>;
+
+	/// Index of the endpoint in `ProcessorEndpoints` that served the latest balance update
+	#[pallet::storage]
+	#[pallet::getter(fn active_endpoint)]
+	pub type ActiveEndpoint<T> = StorageValue<_, u32, OptionQuery>;
+
+	/// Public key of the payment processor
+	///
+	/// If set, balance updates must carry the processor signature over the SCALE encoded
+	/// `(accounts, at)` of the update.
+	#[pallet::storage]
+	#[pallet::getter(fn payment_processor_key)]
+	pub type PaymentProcessorKey<T> = StorageValue<_, ProcessorKey, OptionQuery>;
+
+	/// Attestations of transactions that did not reach the quorum yet
+	///
+	/// `(Transaction ID, Digest) => Attestation`. Attestations with different digests under the
+	/// same transaction ID are conflicting.
+	#[pallet::storage]
+	pub type Attestations<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, Hash, Identity, T::Hash, AttestationOf<T>>;
+
+	/// Transactions settled by the oracles
+	///
+	/// `Transaction ID => Block number`
+	#[pallet::storage]
+	#[pallet::getter(fn settled_transactions)]
+	pub type SettledTransactions<T: Config> =
+		StorageMap<_, Blake2_128Concat, Hash, BlockNumberFor<T>>;
+
+	/// Transfers initiated on-chain
+	///
+	/// `Transaction ID => Transfer`, kept until the records of the transfer are pruned, so that
+	/// its sender can revert it once it is approved.
+	#[pallet::storage]
+	#[pallet::getter(fn transfers)]
+	pub type Transfers<T: Config> = StorageMap<_, Blake2_128Concat, Hash, TransferInfoOf<T>>;
+
+	/// Transactions whose records expire in a block
+	///
+	/// `(Block number, Transaction ID) => ()`, pruned in `on_initialize`.
+	#[pallet::storage]
+	pub type Expirations<T: Config> =
+		StorageDoubleMap<_, Twox64Concat, BlockNumberFor<T>, Identity, Hash, ()>;
+
+	/// Next block whose expirations are pruned
 	#[pallet::storage]
-	#[pallet::getter(fn payment_processor_url)]
-	pub type PaymentProcessorUrl<T> = StorageValue<_, StorageKey, ValueQuery>;
+	pub type PruneCursor<T: Config> = StorageValue<_, BlockNumberFor<T>, OptionQuery>;
+
+	/// Funds reserved by transfers that are waiting for settlement
+	///
+	/// Reserved when a transfer is initiated and released when the oracle settles it.
+	#[pallet::storage]
+	#[pallet::getter(fn pending_reserves)]
+	pub type PendingReserves<T: Config> =
+		StorageMap<_, Blake2_128Concat, AccountIdOf<T>, BalanceOf<T>, ValueQuery>;
+
+	/// Requests waiting to be relayed and settled, if the offchain worker relays transfers
+	///
+	/// `Transaction ID => Block number`. Requests are indexed offchain under
+	/// `TRANSFER_REQUEST_PREFIX`.
+	#[pallet::storage]
+	#[pallet::getter(fn pending_requests)]
+	pub type PendingRequests<T: Config> = StorageMap<_, Identity, Hash, BlockNumberFor<T>>;
 
 	/// Events of this pallet
 	#[pallet::event]
@@ -178,6 +429,42 @@ pub mod pallet {
 		Allowance { from: T::AccountId, to: T::AccountId, amount: BalanceOf<T> },
 		/// Account removed
 		AccountRemoved { account: T::AccountId },
+		/// Oracle attested the finality of a transaction
+		FinalityAttested { oracle: T::AccountId, hash: Hash },
+		/// Oracle attestation differs from the ones already submitted for the transaction
+		ConflictingAttestation { oracle: T::AccountId, hash: Hash },
+		/// Enough oracles attested the transaction, it is settled
+		QuorumReached { hash: Hash, oracles: Vec<T::AccountId> },
+		/// Transaction did not reach the quorum in `RetentionPeriod`, its attestations and
+		/// request were pruned
+		TransactionExpired { hash: Hash },
+		/// Oracle bonded funds on registration, `amount` tops up the bond left from a previous
+		/// registration
+		OracleBonded { oracle: T::AccountId, amount: BalanceOf<T> },
+		/// Oracle was removed, its bond can be withdrawn at `unlocking_at`
+		OracleUnbonding { oracle: T::AccountId, unlocking_at: BlockNumberFor<T> },
+		/// Oracle withdrew its bond
+		BondWithdrawn { oracle: T::AccountId, amount: BalanceOf<T> },
+		/// Oracle sent a heartbeat
+		Heartbeat { oracle: T::AccountId, message: Option<NetworkManagementMessage> },
+		/// Oracle missed its heartbeats and is considered inactive
+		OracleStale { oracle: T::AccountId },
+		/// Payment processor key was rotated, `None` disables the verification
+		PaymentProcessorKeyChanged { key: Option<ProcessorKey> },
+		/// Payment processor endpoints were replaced
+		ProcessorEndpointsChanged { endpoints: BoundedVec<StorageKey, T::MaxEndpoints> },
+		/// Balances were served by another endpoint than the previous update
+		ActiveEndpointChanged { endpoint: u32 },
+		/// Account requested a refresh of its balance
+		BalanceRefreshRequested { account: T::AccountId },
+		/// Refreshed balance of an account that requested it was applied
+		BalanceRefreshed { account: T::AccountId, balance: BalanceOf<T> },
+		/// Account left the queue after `DirtyAccountTimeout` blocks without its requested refresh
+		BalanceRefreshTimedOut { account: T::AccountId },
+		/// On-chain balance was corrected to the balance of the offchain ledger
+		BalanceSynced { account: T::AccountId, old: BalanceOf<T>, new: BalanceOf<T> },
+		/// Balance correction exceeded `DriftAlertThreshold`
+		DriftAlert { account: T::AccountId, old: BalanceOf<T>, new: BalanceOf<T> },
 	}
 
 	// Errors inform users that something went wrong.
@@ -189,6 +476,38 @@ pub mod pallet {
 		AllowanceExceedsBalance,
 		/// Source account is not registered
 		SourceNotRegistered,
+		/// Pallet account can not be registered
+		PalletAccountNotAllowed,
+		/// Transaction is already settled
+		AlreadySettled,
+		/// Oracle already attested the transaction
+		AlreadyAttested,
+		/// Transaction has too many attestations
+		TooManyAttestations,
+		/// Transfer was initiated more than `RetentionPeriod` blocks ago
+		Expired,
+		/// Account has no oracle bond
+		NotBonded,
+		/// Oracle is still registered, its bond is not unbonding
+		NotUnbonding,
+		/// Unbonding period of the bond has not passed yet
+		BondLocked,
+		/// Balance refresh was requested less than `RefreshCooldown` blocks ago
+		RefreshTooSoon,
+		/// Balance sync queue is full
+		RefreshQueueFull,
+		/// Balance update has no balances
+		NoBalances,
+		/// Balances were queried in the future or more than `OffchainWorkerInterval` blocks ago
+		StaleBalances,
+		/// Balances are not signed by the payment processor
+		InvalidProcessorSignature,
+		/// Transaction is not a transfer initiated on-chain, or its records were pruned
+		UnknownTransfer,
+		/// Only the sender of a transfer can revert it
+		NotTransferSender,
+		/// Transfer was not settled as approved
+		TransferNotApproved,
 	}
 
 	#[pallet::validate_unsigned]
@@ -209,12 +528,30 @@ pub mod pallet {
 					return InvalidTransaction::BadProof.into();
 				}
 
-				let UpdateAccountsPayload { public: _public, accounts, last_key: _ } = payload;
+				let UpdateAccountsPayload {
+					public: _public,
+					accounts,
+					last_key: _,
+					at,
+					processor_signature,
+					endpoint: _,
+				} = payload;
 
+				// the batch size is bounded by `MaxBatchSize`
 				if accounts.is_empty() {
 					return InvalidTransaction::Call.into();
-				} else if accounts.len() > MAX_ACCOUNTS as usize {
-					return InvalidTransaction::ExhaustsResources.into();
+				}
+
+				// balances must have been queried recently
+				let now = frame_system::Pallet::<T>::block_number();
+				if *at > now {
+					return InvalidTransaction::Future.into();
+				} else if now - *at > T::OffchainWorkerInterval::get() {
+					return InvalidTransaction::Stale.into();
+				}
+
+				if !Self::verify_processor_signature(accounts, *at, processor_signature.as_ref()) {
+					return InvalidTransaction::BadProof.into();
 				}
 
 				ValidTransaction::with_tag_prefix("ISO8583")
@@ -246,24 +583,96 @@ pub mod pallet {
 		/// It uses `transfer_from` of ERC20-R interface to transfer tokens from the source
 		/// account to the destination account.
 		///
+		/// Each submission is an attestation of the oracle. Transaction is settled once
+		/// `FinalityThreshold` oracles attested the same transaction. Attestations that differ from
+		/// the ones already submitted for the same transaction ID are flagged as conflicting, it is
+		/// up to `AdminOrigin` to slash the oracles behind them.
+		///
 		/// # Errors
 		///
 		/// - If the origin is not an oracle account.
+		/// - If the transaction is already settled.
+		/// - If the oracle already attested the transaction.
 		///
 		/// # Weight
 		///
-		/// - `O(1)`
-		#[pallet::weight(T::DbWeight::get().reads_writes(3, 2))]
+		/// - `O(MaxOracles)`
+		#[pallet::weight(T::DbWeight::get().reads_writes(
+			T::MaxOracles::get() as u64 + 8,
+			T::MaxOracles::get() as u64 + 9,
+		))]
 		#[pallet::call_index(0)]
 		pub fn submit_finality(
 			origin: OriginFor<T>,
 			transaction: FinalisedTransactionOf<T>,
 		) -> DispatchResult {
-			Self::ensure_oracle(origin)?;
+			let oracle = T::OracleOrigin::ensure_origin(origin)?;
+
+			let hash = transaction.hash;
+
+			ensure!(!SettledTransactions::<T>::contains_key(hash), Error::<T>::AlreadySettled);
 
-			Self::process_finalised_transaction(&transaction)?;
+			// settlement records are pruned, late attestations could settle a transaction again
+			let now = frame_system::Pallet::<T>::block_number();
+			ensure!(
+				Self::initiated_at(&transaction.event_id).map_or(true, |initiated_at| {
+					now < initiated_at.saturating_add(T::RetentionPeriod::get())
+				}),
+				Error::<T>::Expired
+			);
+			ensure!(
+				!Attestations::<T>::iter_prefix_values(hash)
+					.any(|attestation| attestation.oracles.contains(&oracle)),
+				Error::<T>::AlreadyAttested
+			);
+
+			let digest = T::Hashing::hash_of(&transaction);
+			let attested = Attestations::<T>::iter_key_prefix(hash).next().is_some();
+			let conflicting = Attestations::<T>::iter_key_prefix(hash).any(|other| other != digest);
+
+			let oracles = Attestations::<T>::try_mutate(
+				hash,
+				digest,
+				|maybe_attestation| -> Result<_, DispatchError> {
+					let attestation = maybe_attestation.get_or_insert_with(|| Attestation {
+						transaction: transaction.clone(),
+						oracles: Default::default(),
+					});
+					attestation
+						.oracles
+						.try_push(oracle.clone())
+						.map_err(|_| Error::<T>::TooManyAttestations)?;
+
+					Ok(attestation.oracles.clone())
+				},
+			)?;
+
+			if !attested {
+				Expirations::<T>::insert(now.saturating_add(T::RetentionPeriod::get()), hash, ());
+			}
 
-			Self::deposit_event(Event::<T>::ProcessedTransaction { transaction });
+			Self::record_attestation(&oracle, &transaction);
+			Self::deposit_event(Event::<T>::FinalityAttested { oracle: oracle.clone(), hash });
+
+			if conflicting {
+				Self::deposit_event(Event::<T>::ConflictingAttestation { oracle, hash });
+			}
+
+			// settle once the quorum is reached
+			if oracles.len() as u32 >= T::FinalityThreshold::get() {
+				let _ = Attestations::<T>::clear_prefix(hash, u32::MAX, None);
+				SettledTransactions::<T>::insert(hash, now);
+				Expirations::<T>::insert(now.saturating_add(T::RetentionPeriod::get()), hash, ());
+				PendingRequests::<T>::remove(hash);
+
+				Self::process_finalised_transaction(&transaction)?;
+
+				Self::deposit_event(Event::<T>::QuorumReached {
+					hash,
+					oracles: oracles.into_inner(),
+				});
+				Self::deposit_event(Event::<T>::ProcessedTransaction { transaction });
+			}
 
 			Ok(())
 		}
@@ -273,10 +682,14 @@ pub mod pallet {
 		/// This function is used by the bank account owners to initiate a transaction with
 		/// their registered on-chain `AccountId`.
 		///
+		/// The transfer is indexed offchain under its transaction ID, the blake2-256 hash of the
+		/// SCALE encoded `(parent hash, event ID)` of its `InitiateTransfer` event, and queued in
+		/// `PendingRequests` if the offchain worker relays transfers.
+		///
 		/// # Errors
 		///
 		/// Transfer will fail if source and destination accounts are not registered in the oracle.
-		#[pallet::weight(T::DbWeight::get().reads_writes(3, 2))]
+		#[pallet::weight(T::DbWeight::get().reads_writes(6, 6))]
 		#[pallet::call_index(1)]
 		pub fn initiate_transfer(
 			origin: OriginFor<T>,
@@ -307,38 +720,63 @@ pub mod pallet {
 
 			// lock funds
 			T::Currency::reserve(&from, amount)?;
+			PendingReserves::<T>::mutate(&from, |reserved| {
+				*reserved = reserved.saturating_add(amount)
+			});
+
+			// balances of both accounts change on the ledger
+			Self::mark_dirty(&from);
+			Self::mark_dirty(&to);
 
+			let event_id = Self::next_event_id();
 			Self::deposit_event(Event::<T>::InitiateTransfer {
 				from: from.clone(),
 				to: to.clone(),
 				amount,
 			});
+			Self::queue_request(event_id, RequestKind::Transfer { from, to, amount });
 
 			Ok(())
 		}
 
 		/// Initiate a revert transaction
 		///
-		/// This function is used by the bank account owners to initiate a revert transaction with
-		/// their registered on-chain `AccountId`.
+		/// This function is used by the bank account owners to revert a transfer they sent with
+		/// their registered on-chain `AccountId`, once it is settled as approved. It is indexed
+		/// and queued like transfers.
 		///
 		/// # Errors
 		///
-		/// Extrinsic is infallible.
-		#[pallet::weight(T::DbWeight::get().writes(0))]
+		/// - If the transfer is unknown or its records were pruned.
+		/// - If the caller is not the sender of the transfer.
+		/// - If the transfer was not settled as approved.
+		#[pallet::weight(T::DbWeight::get().reads_writes(3, 2))]
 		#[pallet::call_index(2)]
 		pub fn initiate_revert(origin: OriginFor<T>, hash: T::Hash) -> DispatchResult {
 			let who = ensure_signed(origin)?;
 
-			Self::deposit_event(Event::<T>::InitiateRevert { who, hash });
+			let reverted =
+				Hash::decode(&mut hash.as_ref()).map_err(|_| Error::<T>::UnknownTransfer)?;
+			let transfer = Transfers::<T>::get(reverted).ok_or(Error::<T>::UnknownTransfer)?;
+			ensure!(transfer.from == who, Error::<T>::NotTransferSender);
+			ensure!(transfer.approved, Error::<T>::TransferNotApproved);
+
+			let event_id = Self::next_event_id();
+			Self::deposit_event(Event::<T>::InitiateRevert { who: who.clone(), hash });
+			Self::queue_request(event_id, RequestKind::Revert { hash: reverted, who });
 
 			Ok(())
 		}
 
 		/// Give allowance to an account
 		///
-		/// Any account can give allowance to any other account.
-		#[pallet::weight(T::DbWeight::get().reads_writes(3, 2))]
+		/// Any registered account can give allowance to any other account.
+		///
+		/// # Errors
+		///
+		/// - If the owner is not registered.
+		/// - If the allowance exceeds the owner's free balance.
+		#[pallet::weight(T::DbWeight::get().reads_writes(4, 2))]
 		#[pallet::call_index(3)]
 		pub fn approve(
 			origin: OriginFor<T>,
@@ -347,6 +785,8 @@ pub mod pallet {
 		) -> DispatchResult {
 			let owner = ensure_signed(origin)?;
 
+			ensure!(Accounts::<T>::contains_key(&owner), Error::<T>::SourceNotRegistered);
+
 			// ensure owner has enough balance
 			ensure!(
 				T::Currency::free_balance(&owner) >= value,
@@ -363,6 +803,11 @@ pub mod pallet {
 		/// Register an account
 		///
 		/// This function is used by the oracle gateway to register an account.
+		///
+		/// # Errors
+		///
+		/// - If the origin is not an oracle account.
+		/// - If the account is the pallet account.
 		#[pallet::weight(T::DbWeight::get().reads_writes(2, 2))]
 		#[pallet::call_index(4)]
 		pub fn register(
@@ -370,10 +815,15 @@ pub mod pallet {
 			account: AccountIdOf<T>,
 			amount: BalanceOf<T>,
 		) -> DispatchResult {
-			Self::ensure_oracle(origin)?;
+			T::OracleOrigin::ensure_origin(origin)?;
+
+			ensure!(account != T::PalletAccount::get(), Error::<T>::PalletAccountNotAllowed);
 
 			// register account and mint initial balance
-			Accounts::<T>::insert(&account, ());
+			Accounts::<T>::insert(
+				&account,
+				AccountInfo::new(frame_system::Pallet::<T>::block_number()),
+			);
 
 			let _ = T::Currency::deposit_creating(&account, amount);
 
@@ -385,13 +835,17 @@ pub mod pallet {
 		/// Remove an account
 		///
 		/// This function is used by the oracle gateway to remove an account. Oracle can remove
-		/// accounts that are not honest or have been compromised.
-		#[pallet::weight(T::DbWeight::get().reads_writes(1, 1))]
+		/// accounts that are not honest or have been compromised. Allowances given by the account
+		/// are removed as well.
+		#[pallet::weight(T::DbWeight::get().reads_writes(2, 4))]
 		#[pallet::call_index(5)]
 		pub fn remove(origin: OriginFor<T>, account: AccountIdOf<T>) -> DispatchResult {
-			Self::ensure_oracle(origin)?;
+			T::OracleOrigin::ensure_origin(origin)?;
 
 			Accounts::<T>::remove(&account);
+			DirtyAccounts::<T>::mutate(|queue| queue.retain(|(queued, _)| queued != &account));
+			RefreshRequests::<T>::remove(&account);
+			let _ = Allowances::<T>::clear_prefix(&account, u32::MAX, None);
 
 			Self::deposit_event(Event::<T>::AccountRemoved { account });
 
@@ -401,199 +855,892 @@ pub mod pallet {
 		/// Submit updated balances
 		///
 		/// This function is used by the offchain worker to submit updated balances to the chain.
-		#[pallet::weight(T::DbWeight::get().reads_writes(payload.accounts.len() as u64, payload.accounts.len() as u64 + 1))]
+		/// Balances are queried at block `at`, and signed by the payment processor if
+		/// `PaymentProcessorKey` is set.
+		#[pallet::weight(T::DbWeight::get().reads_writes(3 * payload.accounts.len() as u64 + 2, 3 * payload.accounts.len() as u64 + 3))]
 		#[pallet::call_index(6)]
 		pub fn update_accounts_unsigned(
 			origin: OriginFor<T>,
-			payload: UpdateAccountsPayload<T::Public, AccountsOf<T>, StorageKey>,
+			payload: UpdateAccountsPayload<T::Public, AccountsOf<T>, StorageKey, BlockNumberFor<T>>,
 			_signature: T::Signature,
 		) -> DispatchResult {
 			// it is an unsigned transaction
 			ensure_none(origin)?;
 
-			let UpdateAccountsPayload { public: _public, accounts, last_key } = payload;
+			let UpdateAccountsPayload {
+				public: _public,
+				accounts,
+				last_key,
+				at: _,
+				processor_signature: _,
+				endpoint,
+			} = payload;
 
-			for (account, balance) in accounts {
-				// do basic check if account is registered
-				if Accounts::<T>::contains_key(&account) {
-					T::Currency::make_free_balance_be(&account, balance);
-				}
-			}
-
-			LastIteratedStorageKey::<T>::put(last_key);
+			Self::apply_balances(accounts, last_key, endpoint);
 
 			Ok(())
 		}
 
 		/// Register an oracle account
 		///
-		/// This function is used to register an oracle account.
+		/// This function is used to register an oracle account. `OracleBond` is reserved from
+		/// the oracle account, an unbonding bond of a previously removed oracle is reused.
+		/// Registering an already registered oracle is a no-op.
 		///
 		/// # Errors
 		///
-		/// Origin must be signed by the root account.
+		/// - Origin must be `AdminOrigin`.
+		/// - If the oracle account can not reserve the bond.
 		///
 		/// # Weight
 		///
 		/// - `O(1)`
-		#[pallet::weight(T::DbWeight::get().writes(1))]
+		#[pallet::weight(T::DbWeight::get().reads_writes(3, 3))]
 		#[pallet::call_index(7)]
 		pub fn register_oracle(origin: OriginFor<T>, account: AccountIdOf<T>) -> DispatchResult {
-			ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			if OracleAccounts::<T>::contains_key(&account) {
+				return Ok(());
+			}
 
-			OracleAccounts::<T>::insert(&account, ());
+			Self::bond(&account)?;
+
+			let now = frame_system::Pallet::<T>::block_number();
+			OracleAccounts::<T>::insert(&account, OracleInfo::new(now));
+			OracleLiveness::<T>::insert(&account, Liveness::new(now));
 
 			Ok(())
 		}
 
 		/// Remove an oracle account
 		///
-		/// This function is used to remove an oracle account.
+		/// This function is used to remove an oracle account. Its bond starts unbonding and can
+		/// be withdrawn with `withdraw_bond` after `UnbondingPeriod`, it can still be slashed
+		/// until then.
 		///
 		/// # Errors
 		///
-		/// Origin must be signed by the root account.
+		/// Origin must be `AdminOrigin`.
 		///
 		/// # Weight
 		///
 		/// - `O(1)`
-		#[pallet::weight(T::DbWeight::get().writes(1))]
+		#[pallet::weight(T::DbWeight::get().reads_writes(1, 2))]
 		#[pallet::call_index(8)]
 		pub fn remove_oracle(origin: OriginFor<T>, account: AccountIdOf<T>) -> DispatchResult {
-			ensure_root(origin)?;
+			T::AdminOrigin::ensure_origin(origin)?;
+
+			if OracleAccounts::<T>::take(&account).is_none() {
+				return Ok(());
+			}
+			OracleLiveness::<T>::remove(&account);
+
+			let unlocking_at =
+				frame_system::Pallet::<T>::block_number().saturating_add(T::UnbondingPeriod::get());
+			let unbonding = OracleBonds::<T>::mutate(&account, |maybe_bond| {
+				maybe_bond.as_mut().map(|bond| bond.unlocking_at = Some(unlocking_at)).is_some()
+			});
 
-			OracleAccounts::<T>::remove(&account);
+			if unbonding {
+				Self::deposit_event(Event::<T>::OracleUnbonding { oracle: account, unlocking_at });
+			}
 
 			Ok(())
 		}
 
-		/// Set payment processor url
+		/// Set payment processor endpoints
 		///
-		/// This function is used to set the payment processor url.
-		#[pallet::weight(T::DbWeight::get().writes(1))]
+		/// Replaces the endpoints the offchain worker queries, the first one is the primary.
+		///
+		/// # Errors
+		///
+		/// Origin must be `AdminOrigin`.
+		#[pallet::weight(T::DbWeight::get().writes(2))]
 		#[pallet::call_index(9)]
-		pub fn set_payment_processor_url(origin: OriginFor<T>, url: StorageKey) -> DispatchResult {
-			ensure_root(origin)?;
+		pub fn set_processor_endpoints(
+			origin: OriginFor<T>,
+			endpoints: BoundedVec<StorageKey, T::MaxEndpoints>,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
 
-			PaymentProcessorUrl::<T>::put(url);
+			ProcessorEndpoints::<T>::put(endpoints.clone());
+			ActiveEndpoint::<T>::kill();
+
+			Self::deposit_event(Event::<T>::ProcessorEndpointsChanged { endpoints });
 
 			Ok(())
 		}
-	}
 
-	#[pallet::hooks]
-	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
-		/// Offchain worker
+		/// Withdraw the bond of a removed oracle
 		///
-		/// Queries balances of all registered accounts and makes sure they are in sync with the
-		/// offchain ledger.
-		fn offchain_worker(now: BlockNumberFor<T>) {
-			// respect interval between offchain worker runs
-			if now % T::OffchainWorkerInterval::get() != Zero::zero() {
-				return;
-			}
+		/// Releases the reserved bond once `UnbondingPeriod` passed since the oracle was removed.
+		///
+		/// # Errors
+		///
+		/// - If the caller has no bond.
+		/// - If the caller is still a registered oracle.
+		/// - If the unbonding period has not passed yet.
+		#[pallet::weight(T::DbWeight::get().reads_writes(2, 2))]
+		#[pallet::call_index(10)]
+		pub fn withdraw_bond(origin: OriginFor<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
 
-			// get last iterated storage key
-			let prefix = storage::storage_prefix(
-				<Pallet<T> as PalletInfoAccess>::name().as_bytes(),
-				b"Accounts",
+			let bond = OracleBonds::<T>::get(&who).ok_or(Error::<T>::NotBonded)?;
+			let unlocking_at = bond.unlocking_at.ok_or(Error::<T>::NotUnbonding)?;
+			ensure!(
+				frame_system::Pallet::<T>::block_number() >= unlocking_at,
+				Error::<T>::BondLocked
 			);
 
-			let mut previous_key = if let Some(key) = LastIteratedStorageKey::<T>::get() {
-				key.into_inner()
-			} else {
-				prefix.to_vec()
-			};
+			OracleBonds::<T>::remove(&who);
+			T::Currency::unreserve(&who, bond.amount);
 
-			let mut count = 0;
+			Self::deposit_event(Event::<T>::BondWithdrawn { oracle: who, amount: bond.amount });
 
-			let mut accounts = Vec::new();
-			while let Some(next) = sp_io::storage::next_key(&previous_key) {
-				// Ensure we are iterating through the correct storage prefix
-				if !next.starts_with(&prefix) {
-					previous_key = prefix.to_vec();
-					break;
-				}
+			Ok(())
+		}
+
+		/// Slash the bond of an oracle
+		///
+		/// Slashed funds are burned, at most the bonded amount is slashed. Bonds of removed
+		/// oracles can be slashed until they are withdrawn.
+		///
+		/// # Errors
+		///
+		/// - Origin must be `AdminOrigin`.
+		/// - If the oracle has no bond.
+		#[pallet::weight(T::DbWeight::get().reads_writes(2, 2))]
+		#[pallet::call_index(11)]
+		pub fn slash_oracle(
+			origin: OriginFor<T>,
+			oracle: AccountIdOf<T>,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
 
-				previous_key = next;
-				count += 1;
+			ensure!(OracleBonds::<T>::contains_key(&oracle), Error::<T>::NotBonded);
 
-				// decode from last 32 bytes of the key
-				if let Ok(account) =
-					AccountIdOf::<T>::decode(&mut &previous_key[previous_key.len() - 32..])
-				{
-					accounts.push(account);
-				}
+			Self::slash_bond(&oracle, amount);
 
-				if count >= MAX_ACCOUNTS {
-					break;
+			Ok(())
+		}
+
+		/// Oracle heartbeat
+		///
+		/// Marks the oracle as seen in the current block. Oracles can attach the 0800/0810
+		/// network management exchange they had with the payment processor: signing off marks
+		/// the oracle inactive, any other heartbeat marks it active again.
+		///
+		/// # Errors
+		///
+		/// Origin must be `OracleOrigin`.
+		#[pallet::weight(T::DbWeight::get().reads_writes(2, 1))]
+		#[pallet::call_index(12)]
+		pub fn heartbeat(
+			origin: OriginFor<T>,
+			message: Option<NetworkManagementMessage>,
+		) -> DispatchResult {
+			let oracle = T::OracleOrigin::ensure_origin(origin)?;
+
+			let now = frame_system::Pallet::<T>::block_number();
+			OracleLiveness::<T>::mutate(&oracle, |maybe_liveness| {
+				let liveness = maybe_liveness.get_or_insert_with(|| Liveness::new(now));
+
+				liveness.last_seen = now;
+				liveness.status = match message {
+					Some(NetworkManagementMessage {
+						code: NetworkManagementCode::SignOff, ..
+					}) => OracleStatus::SignedOff,
+					_ => OracleStatus::Active,
+				};
+				if message.is_some() {
+					liveness.last_message = message.clone();
 				}
-			}
+			});
 
-			// if there are no accounts, early return
-			if accounts.is_empty() {
-				return;
-			}
+			Self::deposit_event(Event::<T>::Heartbeat { oracle, message });
 
-			// fetch and submit updated balances
-			match Self::fetch_and_submit_updated_balances(accounts.clone(), previous_key) {
-				Ok(_) => log::info!(
-					target: "offchain-worker",
-					"Submitted updated balances for {} accounts",
-					accounts.len(),
-				),
-				Err(e) => log::error!(target: "offchain-worker", "Failed: {:?}", e),
-			}
+			Ok(())
 		}
-	}
 
-	#[pallet::genesis_config]
-	#[derive(frame_support::DefaultNoBound)]
-	pub struct GenesisConfig<T: Config> {
-		pub oracle_accounts: Vec<AccountIdOf<T>>,
-		pub accounts: Vec<AccountIdOf<T>>,
-		pub payment_processor_url: Vec<u8>,
-	}
+		/// Set payment processor key
+		///
+		/// Rotates the public key the balance updates of the offchain worker are verified
+		/// against, `None` accepts unsigned balances.
+		///
+		/// # Errors
+		///
+		/// Origin must be `AdminOrigin`.
+		#[pallet::weight(T::DbWeight::get().writes(1))]
+		#[pallet::call_index(13)]
+		pub fn set_payment_processor_key(
+			origin: OriginFor<T>,
+			key: Option<ProcessorKey>,
+		) -> DispatchResult {
+			T::AdminOrigin::ensure_origin(origin)?;
 
-	#[pallet::genesis_build]
-	impl<T: Config> BuildGenesisConfig for GenesisConfig<T> {
-		fn build(&self) {
-			let payment_processor_url: StorageKey =
-				self.payment_processor_url.clone().try_into().unwrap();
+			PaymentProcessorKey::<T>::set(key);
 
-			PaymentProcessorUrl::<T>::put(payment_processor_url);
+			Self::deposit_event(Event::<T>::PaymentProcessorKeyChanged { key });
 
-			for oracle_account in &self.oracle_accounts {
-				OracleAccounts::<T>::insert(oracle_account, ());
-			}
+			Ok(())
+		}
 
-			for account in &self.accounts {
-				Accounts::<T>::insert(account, ());
+		/// Request a refresh of the balance
+		///
+		/// Queues the caller for the next run of the offchain worker, `BalanceRefreshed` is
+		/// emitted once the refreshed balance is applied, `BalanceRefreshTimedOut` if it is not
+		/// applied within `DirtyAccountTimeout` blocks. Accepted requests are free if
+		/// `FeelessRefresh` is set.
+		///
+		/// # Errors
+		///
+		/// - If the caller is not registered.
+		/// - If the caller requested a refresh less than `RefreshCooldown` blocks ago.
+		/// - If the balance sync queue is full.
+		#[pallet::weight(T::DbWeight::get().reads_writes(3, 2))]
+		#[pallet::call_index(14)]
+		pub fn request_balance_refresh(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+
+			ensure!(Accounts::<T>::contains_key(&who), Error::<T>::SourceNotRegistered);
+
+			let now = frame_system::Pallet::<T>::block_number();
+			if let Some(request) = RefreshRequests::<T>::get(&who) {
+				ensure!(
+					now >= request.requested_at.saturating_add(T::RefreshCooldown::get()),
+					Error::<T>::RefreshTooSoon
+				);
 			}
+
+			ensure!(Self::mark_dirty(&who), Error::<T>::RefreshQueueFull);
+			RefreshRequests::<T>::insert(&who, RefreshRequest { requested_at: now, pending: true });
+
+			Self::deposit_event(Event::<T>::BalanceRefreshRequested { account: who });
+
+			let pays = if T::FeelessRefresh::get() { Pays::No } else { Pays::Yes };
+			Ok(pays.into())
 		}
-	}
-}
 
-impl<T: Config> Pallet<T> {
-	/// Ensure origin is registered as an oracle
-	fn ensure_oracle(origin: OriginFor<T>) -> DispatchResult {
-		let signer = ensure_signed(origin)?;
+		/// Submit updated balances from an oracle account
+		///
+		/// Signed counterpart of `update_accounts_unsigned`, used by the offchain worker if
+		/// `BalanceUpdateMode` is signed. Updates are free if it is `SignedFeeless`.
+		///
+		/// # Errors
+		///
+		/// - Origin must be `OracleOrigin`.
+		/// - If there are no balances.
+		/// - If balances were not queried in the last `OffchainWorkerInterval` blocks.
+		/// - If balances are not signed by the payment processor while `PaymentProcessorKey` is
+		///   set.
+		#[pallet::weight(T::DbWeight::get().reads_writes(3 * accounts.len() as u64 + 3, 3 * accounts.len() as u64 + 3))]
+		#[pallet::call_index(15)]
+		pub fn update_accounts(
+			origin: OriginFor<T>,
+			accounts: AccountsOf<T>,
+			last_key: Option<StorageKey>,
+			at: BlockNumberFor<T>,
+			processor_signature: Option<ProcessorSignature>,
+			endpoint: Option<u32>,
+		) -> DispatchResultWithPostInfo {
+			T::OracleOrigin::ensure_origin(origin)?;
 
-		ensure!(Self::oracle_accounts(signer).is_some(), DispatchError::BadOrigin);
+			ensure!(!accounts.is_empty(), Error::<T>::NoBalances);
 
-		Ok(())
+			let now = frame_system::Pallet::<T>::block_number();
+			ensure!(
+				at <= now && now - at <= T::OffchainWorkerInterval::get(),
+				Error::<T>::StaleBalances
+			);
+			ensure!(
+				Self::verify_processor_signature(&accounts, at, processor_signature.as_ref()),
+				Error::<T>::InvalidProcessorSignature
+			);
+
+			Self::apply_balances(accounts, last_key, endpoint);
+
+			let pays = match T::BalanceUpdateMode::get() {
+				SubmissionMode::SignedFeeless => Pays::No,
+				_ => Pays::Yes,
+			};
+			Ok(pays.into())
+		}
 	}
 
-	/// Ensure an account is registered
-	///
-	/// If the account is not registered, register it.
-	fn ensure_registered(account: &AccountIdOf<T>) -> &AccountIdOf<T> {
-		if Accounts::<T>::contains_key(account) && account != &T::PalletAccount::get() {
-			Accounts::<T>::insert(account, ());
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		/// Prune expired transactions, and mark oracles that missed their heartbeats as stale
+		fn on_initialize(now: BlockNumberFor<T>) -> Weight {
+			Self::prune_expired(now).saturating_add(Self::mark_stale_oracles(now))
 		}
 
-		account
-	}
+		/// Offchain worker
+		///
+		/// Queries balances of queued accounts, then balances changed on the offchain ledger and
+		/// periodically balances of all registered accounts, and makes sure they are in sync
+		/// with the offchain ledger.
+		fn offchain_worker(now: BlockNumberFor<T>) {
+			let state = Self::sync_state();
+
+			if state.paused_until.map_or(false, |until| now < until) {
+				return;
+			}
+
+			// respect interval between offchain worker runs, unless a failed run is retried
+			let retry_due = state.retry_at.map_or(false, |at| now >= at);
+			if now % T::OffchainWorkerInterval::get() != Zero::zero() && !retry_due {
+				return;
+			}
+
+			// nodes without a key can not submit balances, it is not a failed run
+			if !Signer::<T, T::AuthorityId>::all_accounts().can_sign() {
+				return;
+			}
+
+			// runs of consecutive blocks must not overlap, every endpoint can time out in a run
+			let mut lock = StorageLock::<Time>::with_deadline(
+				SYNC_LOCK_KEY,
+				Duration::from_millis(
+					T::OffchainRequestDeadline::get()
+						.saturating_mul(T::MaxEndpoints::get() as u64 + 1)
+						.saturating_add(1_000),
+				),
+			);
+			let _guard = match lock.try_lock() {
+				Ok(guard) => guard,
+				Err(_) => {
+					log::debug!(target: "offchain-worker", "Previous run is still in progress");
+					return;
+				},
+			};
+
+			// queued accounts are synced first on every run, the scan gets the rest of the batch.
+			// Balance updates of a run are submitted as one transaction, so the change sync only
+			// runs while the queue is empty.
+			let dirty = DirtyAccounts::<T>::get()
+				.into_iter()
+				.filter(|(_, queued_at)| Self::is_queued(*queued_at, now))
+				.map(|(account, _)| account)
+				.collect::<Vec<_>>();
+			let interval = T::ReconciliationInterval::get();
+			let scan = !T::LedgerSource::CHANGE_FEED ||
+				interval <= T::OffchainWorkerInterval::get() ||
+				(now % interval).is_zero();
+
+			let result = if scan || !dirty.is_empty() {
+				Self::reconcile_accounts(dirty)
+			} else {
+				Self::sync_changes()
+			};
+
+			if let Err(e) = result {
+				log::error!(target: "offchain-worker", "Failed: {:?}", e);
+			}
+
+			Self::record_sync_result(now, state, result);
+
+			if T::TransferRelay::ENABLED {
+				if let Err(e) = Self::relay_transfers() {
+					log::error!(target: "offchain-worker", "Relay failed: {:?}", e);
+				}
+			}
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn try_state(_n: BlockNumberFor<T>) -> Result<(), sp_runtime::TryRuntimeError> {
+			Self::do_try_state()
+		}
+	}
+
+	#[pallet::genesis_config]
+	#[derive(frame_support::DefaultNoBound)]
+	pub struct GenesisConfig<T: Config> {
+		pub oracle_accounts: Vec<AccountIdOf<T>>,
+		pub accounts: Vec<AccountIdOf<T>>,
+		pub processor_endpoints: Vec<Vec<u8>>,
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> BuildGenesisConfig for GenesisConfig<T> {
+		fn build(&self) {
+			assert!(
+				self.processor_endpoints.len() <= T::MaxEndpoints::get() as usize,
+				"At most `MaxEndpoints` ({}) processor endpoints are allowed, got {}",
+				T::MaxEndpoints::get(),
+				self.processor_endpoints.len(),
+			);
+
+			let processor_endpoints = self
+				.processor_endpoints
+				.iter()
+				.map(|url| {
+					assert!(
+						url.len() <= StorageKey::bound(),
+						"Processor endpoints are at most {} bytes long, got {}",
+						StorageKey::bound(),
+						url.len(),
+					);
+					StorageKey::truncate_from(url.clone())
+				})
+				.collect::<Vec<_>>();
+
+			ProcessorEndpoints::<T>::put(BoundedVec::<_, T::MaxEndpoints>::truncate_from(
+				processor_endpoints,
+			));
+
+			let now = frame_system::Pallet::<T>::block_number();
+
+			for oracle_account in &self.oracle_accounts {
+				Pallet::<T>::bond(oracle_account).expect("Oracle accounts can reserve the bond");
+				OracleAccounts::<T>::insert(oracle_account, OracleInfo::new(now));
+				OracleLiveness::<T>::insert(oracle_account, Liveness::new(now));
+			}
+
+			for account in &self.accounts {
+				Accounts::<T>::insert(account, AccountInfo::new(now));
+			}
+		}
+	}
+}
+
+impl<T: Config> Pallet<T> {
+	/// Storage prefix of `Accounts`
+	fn accounts_prefix() -> [u8; 32] {
+		storage::storage_prefix(<Pallet<T> as PalletInfoAccess>::name().as_bytes(), b"Accounts")
+	}
+
+	/// Apply balances of the offchain ledger to registered accounts
+	fn apply_balances(
+		accounts: AccountsOf<T>,
+		last_key: Option<StorageKey>,
+		endpoint: Option<u32>,
+	) {
+		let now = frame_system::Pallet::<T>::block_number();
+
+		// synced accounts leave the queue, so do accounts the processor did not return in time
+		DirtyAccounts::<T>::mutate(|queue| {
+			queue.retain(|(queued, _)| !accounts.iter().any(|(account, _)| account == queued));
+			Self::drop_timed_out(queue, now);
+		});
+
+		for (account, balance) in accounts {
+			// do basic check if account is registered
+			let balance = Self::free_balance_for(&account, balance);
+			let synced = Accounts::<T>::mutate(&account, |maybe_info| {
+				maybe_info.as_mut().map(|info| {
+					let old = T::Currency::free_balance(&account);
+					T::Currency::make_free_balance_be(&account, balance);
+					info.last_synced_at = Some(now);
+					old
+				})
+			});
+
+			if let Some(old) = synced {
+				if old != balance {
+					Self::record_drift(&account, old, balance);
+				}
+
+				// requested refreshes are reported once applied
+				let requested = RefreshRequests::<T>::mutate(&account, |maybe_request| {
+					maybe_request
+						.as_mut()
+						.map_or(false, |request| core::mem::replace(&mut request.pending, false))
+				});
+				if requested {
+					Self::deposit_event(Event::<T>::BalanceRefreshed { account, balance });
+				}
+			}
+		}
+
+		// balances fetched from the url local to the node say nothing about the endpoints
+		if let Some(endpoint) = endpoint {
+			if ActiveEndpoint::<T>::get() != Some(endpoint) {
+				ActiveEndpoint::<T>::put(endpoint);
+				Self::deposit_event(Event::<T>::ActiveEndpointChanged { endpoint });
+			}
+		}
+
+		// changed balances leave the iteration where it is, only keys of `Accounts` can be
+		// used to resume it
+		match last_key {
+			Some(key) if key.starts_with(&Self::accounts_prefix()) =>
+				LastIteratedStorageKey::<T>::put(key),
+			Some(_) => LastIteratedStorageKey::<T>::kill(),
+			None => {},
+		}
+	}
+
+	/// ID of the next event of the current block
+	///
+	/// `event_id` is the encoded `(block number, event index)`, see `initiated_at`.
+	fn next_event_id() -> EventId {
+		let block: u32 = frame_system::Pallet::<T>::block_number().saturated_into();
+
+		EventId::truncate_from((block, frame_system::Pallet::<T>::event_count()).encode())
+	}
+
+	/// Index the request of the event `event_id` offchain, and queue it for the relay of the
+	/// offchain worker if it relays transfers
+	///
+	/// Requests are indexed under their transaction ID until their records are pruned,
+	/// whether the offchain worker or an external oracle relays them. On-chain, only the
+	/// transaction ID and the sender of transfers are kept, for their reverts. IDs depend on the
+	/// parent hash, so a request included again after a reorg gets a new one.
+	fn queue_request(event_id: EventId, kind: RequestKind<AccountIdOf<T>, BalanceOf<T>>) {
+		let now = frame_system::Pallet::<T>::block_number();
+		let hash = Hash::from(sp_io::hashing::blake2_256(
+			&(frame_system::Pallet::<T>::parent_hash(), &event_id).encode(),
+		));
+
+		if let RequestKind::Transfer { from, .. } = &kind {
+			Transfers::<T>::insert(hash, TransferInfo { from: from.clone(), approved: false });
+		}
+
+		sp_io::offchain_index::set(
+			&Self::request_key(&hash),
+			&TransferRequest { event_id, kind }.encode(),
+		);
+
+		if T::TransferRelay::ENABLED {
+			PendingRequests::<T>::insert(hash, now);
+		}
+
+		Expirations::<T>::insert(now.saturating_add(T::RetentionPeriod::get()), hash, ());
+	}
+
+	/// Offchain index key of the request of transaction `hash`
+	fn request_key(hash: &Hash) -> Vec<u8> {
+		[TRANSFER_REQUEST_PREFIX, hash.as_bytes()].concat()
+	}
+
+	/// Free balance of `account` for its `balance` on the offchain ledger
+	///
+	/// Funds of pending transfers stay reserved until the transfers are settled, balances of
+	/// the ledger view still include them.
+	fn free_balance_for(account: &AccountIdOf<T>, balance: BalanceOf<T>) -> BalanceOf<T> {
+		match T::BalanceView::get() {
+			LedgerView::Available => balance,
+			LedgerView::Ledger => balance.saturating_sub(PendingReserves::<T>::get(account)),
+		}
+	}
+
+	/// Record the correction of the balance of `account` from `old` to `new`
+	fn record_drift(account: &AccountIdOf<T>, old: BalanceOf<T>, new: BalanceOf<T>) {
+		let drift = if new > old { new - old } else { old - new };
+
+		BalanceDrift::<T>::mutate(|stats| {
+			if new > old {
+				stats.credited = stats.credited.saturating_add(drift);
+			} else {
+				stats.debited = stats.debited.saturating_add(drift);
+			}
+			stats.corrections = stats.corrections.saturating_add(1);
+		});
+
+		Self::deposit_event(Event::<T>::BalanceSynced { account: account.clone(), old, new });
+
+		if drift > T::DriftAlertThreshold::get() {
+			Self::deposit_event(Event::<T>::DriftAlert { account: account.clone(), old, new });
+		}
+	}
+
+	/// Queue a registered account for the next balance sync, returns whether it is queued
+	pub(crate) fn mark_dirty(account: &AccountIdOf<T>) -> bool {
+		if !Accounts::<T>::contains_key(account) {
+			return false;
+		}
+
+		let now = frame_system::Pallet::<T>::block_number();
+
+		// the scan of `Accounts` reaches accounts which do not fit
+		DirtyAccounts::<T>::mutate(|queue| {
+			Self::drop_timed_out(queue, now);
+			queue.iter().any(|(queued, _)| queued == account) ||
+				queue.try_push((account.clone(), now)).is_ok()
+		})
+	}
+
+	/// Drop accounts which waited `DirtyAccountTimeout` blocks for a balance sync from the queue
+	///
+	/// Their pending refresh requests are reported as timed out, they can be requested again.
+	fn drop_timed_out(queue: &mut DirtyAccountsOf<T>, now: BlockNumberFor<T>) {
+		queue.retain(|(account, queued_at)| {
+			if Self::is_queued(*queued_at, now) {
+				return true;
+			}
+
+			let requested = RefreshRequests::<T>::mutate(account, |maybe_request| {
+				maybe_request
+					.as_mut()
+					.map_or(false, |request| core::mem::replace(&mut request.pending, false))
+			});
+			if requested {
+				Self::deposit_event(Event::<T>::BalanceRefreshTimedOut {
+					account: account.clone(),
+				});
+			}
+
+			false
+		});
+	}
+
+	/// Whether an account queued in block `queued_at` still waits for a balance sync in `now`
+	fn is_queued(queued_at: BlockNumberFor<T>, now: BlockNumberFor<T>) -> bool {
+		now.saturating_sub(queued_at) < T::DirtyAccountTimeout::get()
+	}
+
+	/// Prune transactions that expired up to block `now`
+	///
+	/// Expirations are pruned block by block, at most `MaxPrunedPerBlock` of them per call. A
+	/// block without expirations counts as one, the rest is left for the following blocks.
+	fn prune_expired(now: BlockNumberFor<T>) -> Weight {
+		let mut weight = T::DbWeight::get().reads_writes(1, 1);
+		let mut budget = T::MaxPrunedPerBlock::get();
+		let mut cursor = PruneCursor::<T>::get().unwrap_or(now);
+
+		while cursor <= now && budget > 0 {
+			let expired = Expirations::<T>::iter_key_prefix(cursor)
+				.take(budget as usize)
+				.collect::<Vec<_>>();
+			weight.saturating_accrue(T::DbWeight::get().reads(1));
+
+			for hash in &expired {
+				Expirations::<T>::remove(cursor, hash);
+				weight.saturating_accrue(Self::prune(hash, cursor));
+			}
+
+			// move on once all expirations of the block are pruned
+			if (expired.len() as u32) < budget {
+				cursor.saturating_inc();
+			}
+			budget = budget.saturating_sub((expired.len() as u32).max(1));
+		}
+
+		PruneCursor::<T>::put(cursor);
+
+		weight
+	}
+
+	/// Prune the records of transaction `hash` that expired at block `at`
+	///
+	/// The settlement record and the request are kept `RetentionPeriod` blocks after the
+	/// settlement, so that reverts can be relayed. A transaction that did not reach the quorum
+	/// loses its attestations and request, and counts as timed out in the statistics of the
+	/// registered oracles that did not attest it.
+	fn prune(hash: &Hash, at: BlockNumberFor<T>) -> Weight {
+		if let Some(settled_at) = SettledTransactions::<T>::get(hash) {
+			if settled_at.saturating_add(T::RetentionPeriod::get()) <= at {
+				SettledTransactions::<T>::remove(hash);
+				Transfers::<T>::remove(hash);
+				sp_io::offchain_index::clear(&Self::request_key(hash));
+			}
+
+			return T::DbWeight::get().reads_writes(2, 3);
+		}
+
+		let attesters = Attestations::<T>::iter_prefix_values(hash)
+			.flat_map(|attestation| attestation.oracles)
+			.collect::<Vec<_>>();
+		let pending = PendingRequests::<T>::take(hash).is_some();
+
+		let _ = Attestations::<T>::clear_prefix(hash, u32::MAX, None);
+		Transfers::<T>::remove(hash);
+		sp_io::offchain_index::clear(&Self::request_key(hash));
+
+		// registered oracles that did not attest the transaction missed it
+		let mut oracles = 0u64;
+		if !attesters.is_empty() || pending {
+			for oracle in OracleAccounts::<T>::iter_keys() {
+				oracles.saturating_inc();
+				if !attesters.contains(&oracle) {
+					Self::record_missed(&oracle);
+				}
+			}
+
+			Self::deposit_event(Event::<T>::TransactionExpired { hash: *hash });
+		}
+
+		T::DbWeight::get().reads_writes(
+			T::MaxOracles::get() as u64 + oracles + 3,
+			T::MaxOracles::get() as u64 + oracles + 4,
+		)
+	}
+
+	/// Mark oracles that missed their heartbeats as stale
+	///
+	/// Runs every `HeartbeatTimeout` blocks.
+	fn mark_stale_oracles(now: BlockNumberFor<T>) -> Weight {
+		let timeout = T::HeartbeatTimeout::get();
+
+		if timeout.is_zero() || now % timeout != Zero::zero() {
+			return Weight::zero();
+		}
+
+		let mut reads = 0u64;
+		let stale = OracleLiveness::<T>::iter()
+			.inspect(|_| reads.saturating_inc())
+			.filter(|(_, liveness)| {
+				liveness.status == OracleStatus::Active &&
+					now.saturating_sub(liveness.last_seen) >= timeout
+			})
+			.map(|(oracle, _)| oracle)
+			.collect::<Vec<_>>();
+
+		for oracle in &stale {
+			OracleLiveness::<T>::mutate_extant(oracle, |liveness| {
+				liveness.status = OracleStatus::Stale
+			});
+			Self::deposit_event(Event::<T>::OracleStale { oracle: oracle.clone() });
+		}
+
+		T::DbWeight::get().reads_writes(reads, stale.len() as u64)
+	}
+
+	/// Liveness of an oracle, `None` if it is not registered or registered before heartbeats
+	pub fn oracle_health(oracle: AccountIdOf<T>) -> Option<LivenessOf<T>> {
+		OracleLiveness::<T>::get(oracle)
+	}
+
+	/// Liveness of all registered oracles
+	pub fn oracles_health() -> Vec<(AccountIdOf<T>, Option<LivenessOf<T>>)> {
+		OracleAccounts::<T>::iter_keys()
+			.map(|oracle| {
+				let liveness = OracleLiveness::<T>::get(&oracle);
+				(oracle, liveness)
+			})
+			.collect()
+	}
+
+	/// Settlement statistics of all oracles that attested at least once
+	pub fn oracles_stats() -> Vec<(AccountIdOf<T>, OracleStatsOf<T>)> {
+		OracleStatistics::<T>::iter().collect()
+	}
+
+	/// Payment processor endpoints, with whether the latest balance update was served by them
+	pub fn processor_endpoints_status() -> Vec<(Vec<u8>, bool)> {
+		let active = ActiveEndpoint::<T>::get();
+
+		ProcessorEndpoints::<T>::get()
+			.into_iter()
+			.enumerate()
+			.map(|(index, url)| (url.into_inner(), active == Some(index as u32)))
+			.collect()
+	}
+
+	/// Block in which the transfer of `event_id` was initiated
+	///
+	/// `event_id` is the encoded `(block number, event index)` of the `InitiateTransfer` event.
+	fn initiated_at(event_id: &EventId) -> Option<BlockNumberFor<T>> {
+		<(u32, u32)>::decode(&mut &event_id[..]).ok().map(|(block, _)| block.into())
+	}
+
+	/// Record an attestation of `transaction` in the statistics of `oracle`
+	fn record_attestation(oracle: &AccountIdOf<T>, transaction: &FinalisedTransactionOf<T>) {
+		let now = frame_system::Pallet::<T>::block_number();
+		let approved = transaction.status == ISO8583Status::Approved;
+		let latency = Self::initiated_at(&transaction.event_id)
+			.filter(|initiated_at| *initiated_at <= now)
+			.map(|initiated_at| now - initiated_at);
+		let timed_out = latency.map_or(false, |latency| latency > T::SettlementTimeout::get());
+
+		Self::record_stats(oracle, |stats| stats.record(approved, latency, timed_out));
+	}
+
+	/// Record a request that expired before `oracle` attested it as timed out
+	fn record_missed(oracle: &AccountIdOf<T>) {
+		Self::record_stats(oracle, |stats| stats.record_missed());
+	}
+
+	/// Record in the total and the current window of the statistics of `oracle`
+	fn record_stats(
+		oracle: &AccountIdOf<T>,
+		record: impl Fn(&mut SettlementStats<BlockNumberFor<T>>),
+	) {
+		let now = frame_system::Pallet::<T>::block_number();
+		let window_length = T::StatsWindowLength::get();
+		let window_start =
+			if window_length.is_zero() { Zero::zero() } else { now - now % window_length };
+
+		OracleStatistics::<T>::mutate(oracle, |stats| {
+			record(&mut stats.total);
+
+			match stats.windows.last_mut() {
+				Some(window) if window.start == window_start => record(&mut window.stats),
+				_ => {
+					let mut window = StatsWindow { start: window_start, stats: Default::default() };
+					record(&mut window.stats);
+
+					// drop the oldest window
+					if stats.windows.is_full() {
+						stats.windows.remove(0);
+					}
+					let _ = stats.windows.try_push(window);
+				},
+			}
+		});
+	}
+
+	/// Bond `OracleBond` from an oracle account
+	///
+	/// Tops up the unbonding bond of a previously removed oracle, if any.
+	fn bond(oracle: &AccountIdOf<T>) -> DispatchResult {
+		let amount =
+			OracleBonds::<T>::try_mutate(oracle, |maybe_bond| -> Result<_, DispatchError> {
+				let bonded = maybe_bond.as_ref().map(|bond| bond.amount).unwrap_or_default();
+				let top_up = T::OracleBond::get().saturating_sub(bonded);
+
+				T::Currency::reserve(oracle, top_up)?;
+				*maybe_bond = Some(Bond::new(bonded.saturating_add(top_up)));
+
+				Ok(top_up)
+			})?;
+
+		Self::deposit_event(Event::<T>::OracleBonded { oracle: oracle.clone(), amount });
+
+		Ok(())
+	}
+
+	/// Slash up to `amount` from the bond of an oracle
+	///
+	/// Emits `DeductFunds` with the slashed amount. An unbonding bond that is slashed completely
+	/// is removed.
+	fn slash_bond(oracle: &AccountIdOf<T>, amount: BalanceOf<T>) {
+		let slashed = OracleBonds::<T>::mutate_exists(oracle, |maybe_bond| {
+			let mut slashed = Zero::zero();
+
+			if let Some(bond) = maybe_bond {
+				let (imbalance, _) = T::Currency::slash_reserved(oracle, amount.min(bond.amount));
+				slashed = imbalance.peek();
+				bond.amount = bond.amount.saturating_sub(slashed);
+
+				// nothing left to withdraw
+				if bond.amount.is_zero() && bond.unlocking_at.is_some() {
+					*maybe_bond = None;
+				}
+			}
+
+			slashed
+		});
+
+		if !slashed.is_zero() {
+			Self::deposit_event(Event::<T>::DeductFunds { who: oracle.clone(), amount: slashed });
+		}
+	}
+
+	/// Release up to `amount` of the funds reserved for pending transfers of `account`
+	///
+	/// Oracle bonds are reserved as well, only what `PendingReserves` accounts for is released.
+	fn release_pending(account: &AccountIdOf<T>, amount: BalanceOf<T>) {
+		PendingReserves::<T>::mutate_exists(account, |maybe_reserved| {
+			let reserved = maybe_reserved.unwrap_or_default();
+			let released = amount.min(reserved);
+			let remaining = T::Currency::unreserve(account, released);
+			let reserved = reserved.saturating_sub(released.saturating_sub(remaining));
+			*maybe_reserved = Some(reserved).filter(|reserved| !reserved.is_zero());
+		});
+	}
 
 	/// Process a finalised transaction
 	///
@@ -603,23 +1750,35 @@ impl<T: Config> Pallet<T> {
 	fn process_finalised_transaction(transaction: &FinalisedTransactionOf<T>) -> DispatchResult {
 		let pallet_account = T::PalletAccount::get();
 
-		// early return if this is a failed transaction
+		// failed transfers only release the funds reserved for them
 		if let ISO8583Status::Failed(_) = transaction.status {
+			if transaction.from != pallet_account {
+				Self::release_pending(&transaction.from, transaction.amount);
+			}
 			return Ok(());
 		}
 
-		// ensure accounts are registered
-		let from = Self::ensure_registered(&transaction.from);
-		let to = Self::ensure_registered(&transaction.to);
+		// settlements do not register accounts, unregistered ones are not queued
+		let (from, to) = (&transaction.from, &transaction.to);
+
+		Self::mark_dirty(from);
+		Self::mark_dirty(to);
 
 		// we don't distinguish between transfer and reverse transactions
 		if transaction.status == ISO8583Status::Approved {
+			// approved transfers can be reverted by their sender
+			Transfers::<T>::mutate(transaction.hash, |maybe_transfer| {
+				if let Some(transfer) = maybe_transfer {
+					transfer.approved = true;
+				}
+			});
+
 			// this happens when accounts are not registered on-chain
 			if transaction.from == pallet_account {
 				let _ = T::Currency::deposit_creating(to, transaction.amount);
 			} else {
-				// unreserve funds and transfer
-				let _ = T::Currency::unreserve(from, transaction.amount);
+				// unreserve funds of pending transfers and transfer, bonds stay reserved
+				Self::release_pending(from, transaction.amount);
 				Self::transfer_from(&T::PalletAccount::get(), from, to, transaction.amount)?;
 			}
 		}
@@ -628,47 +1787,474 @@ impl<T: Config> Pallet<T> {
 	}
 }
 
+/// Invariants of the pallet
+///
+/// Checked by `try_state` and at the end of tests.
+#[cfg(any(feature = "try-runtime", test))]
+impl<T: Config> Pallet<T> {
+	/// Check all invariants of the pallet
+	pub(crate) fn do_try_state() -> Result<(), sp_runtime::TryRuntimeError> {
+		Self::try_state_reserves()?;
+		Self::try_state_bonds()?;
+		Self::try_state_liveness()?;
+		Self::try_state_allowances()?;
+		Self::try_state_accounts()?;
+		Self::try_state_dirty_accounts()?;
+		Self::try_state_last_key()
+	}
+
+	/// Reserved balance of every registered account, account with pending transfers, or bonded
+	/// oracle, is explained by its pending transfers and oracle bond.
+	fn try_state_reserves() -> Result<(), sp_runtime::TryRuntimeError> {
+		for account in Accounts::<T>::iter_keys()
+			.chain(PendingReserves::<T>::iter_keys())
+			.chain(OracleBonds::<T>::iter_keys())
+		{
+			let bonded =
+				OracleBonds::<T>::get(&account).map(|bond| bond.amount).unwrap_or_default();
+			ensure!(
+				T::Currency::reserved_balance(&account) ==
+					PendingReserves::<T>::get(&account).saturating_add(bonded),
+				"Reserved balance is not explained by pending transfers and oracle bond"
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Bonds of registered oracles are not unbonding, bonds of removed oracles are.
+	fn try_state_bonds() -> Result<(), sp_runtime::TryRuntimeError> {
+		for (oracle, bond) in OracleBonds::<T>::iter() {
+			ensure!(
+				OracleAccounts::<T>::contains_key(&oracle) == bond.unlocking_at.is_none(),
+				"Oracle bond does not match the oracle registration"
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Liveness is tracked only for registered oracles.
+	fn try_state_liveness() -> Result<(), sp_runtime::TryRuntimeError> {
+		for oracle in OracleLiveness::<T>::iter_keys() {
+			ensure!(OracleAccounts::<T>::contains_key(&oracle), "Liveness of a removed oracle");
+		}
+
+		Ok(())
+	}
+
+	/// Every allowance is given by a registered account.
+	fn try_state_allowances() -> Result<(), sp_runtime::TryRuntimeError> {
+		for (owner, _) in Allowances::<T>::iter_keys() {
+			ensure!(Accounts::<T>::contains_key(&owner), "Allowance of an unregistered account");
+		}
+
+		Ok(())
+	}
+
+	/// Pallet account is never registered.
+	fn try_state_accounts() -> Result<(), sp_runtime::TryRuntimeError> {
+		ensure!(
+			!Accounts::<T>::contains_key(T::PalletAccount::get()),
+			"Pallet account is registered"
+		);
+
+		Ok(())
+	}
+
+	/// Only registered accounts are queued for a balance sync, once. Accounts waiting for a
+	/// requested refresh are queued.
+	fn try_state_dirty_accounts() -> Result<(), sp_runtime::TryRuntimeError> {
+		let queue = DirtyAccounts::<T>::get()
+			.into_iter()
+			.map(|(account, _)| account)
+			.collect::<Vec<_>>();
+		for (index, account) in queue.iter().enumerate() {
+			ensure!(Accounts::<T>::contains_key(account), "Unregistered account is queued");
+			ensure!(!queue[..index].contains(account), "Account is queued twice");
+		}
+
+		for (account, request) in RefreshRequests::<T>::iter() {
+			ensure!(Accounts::<T>::contains_key(&account), "Refresh of an unregistered account");
+			ensure!(
+				!request.pending || queue.contains(&account),
+				"Requested refresh is not queued"
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Last iterated storage key points into `Accounts`.
+	fn try_state_last_key() -> Result<(), sp_runtime::TryRuntimeError> {
+		if let Some(key) = LastIteratedStorageKey::<T>::get() {
+			ensure!(
+				key.starts_with(&Self::accounts_prefix()),
+				"Last iterated storage key is outside of `Accounts`"
+			);
+		}
+
+		Ok(())
+	}
+}
+
 /// Functions used by offchain worker
 impl<T: Config> Pallet<T> {
+	/// Payment processor endpoints queried by this node, in order of preference
+	///
+	/// The url set for this node in offchain local storage comes before `ProcessorEndpoints`, so
+	/// each node can query the processor instance next to it. On-chain endpoints come with their
+	/// index in `ProcessorEndpoints`.
+	pub fn endpoints() -> Vec<(Option<u32>, Vec<u8>)> {
+		let local =
+			sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, PAYMENT_PROCESSOR_URL_KEY)
+				.filter(|url| !url.is_empty())
+				.map(|url| (None, url));
+
+		local
+			.into_iter()
+			.chain(
+				ProcessorEndpoints::<T>::get()
+					.into_iter()
+					.enumerate()
+					.map(|(index, url)| (Some(index as u32), url.into_inner())),
+			)
+			.collect()
+	}
+
+	/// Failover state of the endpoints of this node
+	pub fn failover_state() -> FailoverStateOf<T> {
+		StorageValueRef::persistent(FAILOVER_STATE_KEY)
+			.get()
+			.ok()
+			.flatten()
+			.unwrap_or_default()
+	}
+
+	/// Change cursor of the payment processor, following the last synced changes
+	pub fn change_cursor() -> Option<Vec<u8>> {
+		StorageValueRef::persistent(CHANGE_CURSOR_KEY).get().ok().flatten()
+	}
+
+	/// Request of transaction `hash` indexed offchain, until its records are pruned
+	///
+	/// Offchain context only, the node must run with offchain indexing enabled.
+	pub fn transfer_request(hash: &Hash) -> Option<TransferRequestOf<T>> {
+		StorageValueRef::persistent(&Self::request_key(hash)).get().ok().flatten()
+	}
+
+	/// Fetch from the first available endpoint
+	///
+	/// Starts with the endpoint in use, and moves on to the next one on timeouts and `5xx`
+	/// responses. While failed over, the primary is probed every `EndpointProbeInterval` blocks
+	/// and used again once it answers.
+	fn fetch_with_failover<R>(
+		at: BlockNumberFor<T>,
+		fetch: impl Fn(&[u8]) -> Result<R, LedgerError>,
+	) -> Result<(R, Option<u32>), &'static str> {
+		let endpoints = Self::endpoints();
+		if endpoints.is_empty() {
+			return Err("No payment processor endpoints");
+		}
+
+		let mut state = Self::failover_state();
+		state.failures.resize(endpoints.len(), 0);
+		if state.active as usize >= endpoints.len() {
+			state.active = 0;
+		}
+
+		let probe = state.active != 0 &&
+			state.probed_at.map_or(true, |probed_at| {
+				at >= probed_at.saturating_add(T::EndpointProbeInterval::get())
+			});
+		let start = if probe {
+			state.probed_at = Some(at);
+			0
+		} else {
+			state.active as usize
+		};
+
+		let mut result = Err("All payment processor endpoints are unavailable");
+
+		for index in (0..endpoints.len()).map(|offset| (start + offset) % endpoints.len()) {
+			let (endpoint, url) = &endpoints[index];
+
+			match fetch(url) {
+				Ok(balances) => {
+					if index != state.active as usize {
+						log::warn!(
+							target: "offchain-worker",
+							"Switching payment processor endpoint from {} to {}",
+							state.active,
+							index,
+						);
+						state.active = index as u32;
+					}
+					// the primary is probed from the block it was left
+					state.probed_at = if index == 0 { None } else { state.probed_at.or(Some(at)) };
+					state.failures[index] = 0;
+
+					result = Ok((balances, *endpoint));
+					break;
+				},
+				Err(e) if e.is_unavailable() => {
+					state.failures[index] = state.failures[index].saturating_add(1);
+					log::warn!(
+						target: "offchain-worker",
+						"Payment processor endpoint {} is unavailable ({} failures): {:?}",
+						index,
+						state.failures[index],
+						e,
+					);
+				},
+				Err(e) => {
+					log::error!(target: "offchain-worker", "Failed to fetch balances: {:?}", e);
+					result = Err("Failed to fetch balances");
+					break;
+				},
+			}
+		}
+
+		StorageValueRef::persistent(FAILOVER_STATE_KEY).set(&state);
+
+		result
+	}
+
+	/// Balance sync state of this node
+	pub fn sync_state() -> SyncStateOf<T> {
+		StorageValueRef::persistent(SYNC_STATE_KEY)
+			.get()
+			.ok()
+			.flatten()
+			.unwrap_or_default()
+	}
+
+	/// Schedule the retry of a failed run, or pause syncing after `MaxSyncRetries` failures
+	fn record_sync_result(
+		now: BlockNumberFor<T>,
+		mut state: SyncStateOf<T>,
+		result: Result<(), &'static str>,
+	) {
+		match result {
+			Ok(()) => {
+				if state.failures > 0 {
+					log::info!(
+						target: "offchain-worker",
+						"Balance sync recovered after {} failed runs",
+						state.failures,
+					);
+				}
+				state = Default::default();
+			},
+			Err(e) => {
+				state.failures = state.failures.saturating_add(1);
+
+				if state.failures >= T::MaxSyncRetries::get() {
+					let until = now.saturating_add(T::SyncPauseDuration::get());
+					log::warn!(
+						target: "offchain-worker",
+						"Pausing balance sync until block {:?} after {} failed runs, last error: {}",
+						until,
+						state.failures,
+						e,
+					);
+					state = SyncState { failures: 0, retry_at: None, paused_until: Some(until) };
+				} else {
+					let backoff = 2_u32.saturating_pow(state.failures - 1);
+					state.retry_at = Some(now.saturating_add(backoff.into()));
+					state.paused_until = None;
+				}
+			},
+		}
+
+		StorageValueRef::persistent(SYNC_STATE_KEY).set(&state);
+	}
+
+	/// Verify the payment processor signature over balances queried at block `at`
+	///
+	/// Any balances are accepted while `PaymentProcessorKey` is not set.
+	fn verify_processor_signature(
+		accounts: &AccountsOf<T>,
+		at: BlockNumberFor<T>,
+		signature: Option<&ProcessorSignature>,
+	) -> bool {
+		match (PaymentProcessorKey::<T>::get(), signature) {
+			(None, _) => true,
+			(Some(key), Some(signature)) => (accounts, at)
+				.using_encoded(|message| sp_io::crypto::sr25519_verify(signature, message, &key)),
+			(Some(_), None) => false,
+		}
+	}
+
+	/// Sync balances of the `queued` accounts and of the next registered accounts
+	///
+	/// Queued accounts come first, the scan of `Accounts` continues with the rest of the
+	/// `MaxBatchSize` batch.
+	fn reconcile_accounts(mut queued: Vec<AccountIdOf<T>>) -> Result<(), &'static str> {
+		let batch_size = T::MaxBatchSize::get() as usize;
+		queued.truncate(batch_size);
+		let scan_size = batch_size - queued.len();
+
+		// continue after the last iterated storage key
+		let mut keys = match LastIteratedStorageKey::<T>::get() {
+			Some(key) => Accounts::<T>::iter_keys_from(key.into_inner()),
+			None => Accounts::<T>::iter_keys(),
+		};
+		let mut scanned = keys.by_ref().take(scan_size).collect::<Vec<_>>();
+
+		// start over once all accounts were iterated
+		if scanned.is_empty() && scan_size > 0 {
+			keys = Accounts::<T>::iter_keys();
+			scanned = keys.by_ref().take(scan_size).collect();
+		}
+
+		// if there are no accounts, early return
+		if queued.is_empty() && scanned.is_empty() {
+			return Ok(());
+		}
+
+		// a full batch of queued accounts leaves the iteration where it is, the next run starts
+		// over if the scan reached the end
+		let previous_key = if scan_size == 0 {
+			None
+		} else if scanned.len() < scan_size {
+			Some(Self::accounts_prefix().to_vec())
+		} else {
+			Some(keys.last_raw_key().to_vec())
+		};
+
+		let (queued_count, scanned_count) = (queued.len(), scanned.len());
+		scanned.retain(|account| !queued.contains(account));
+		let mut accounts = queued;
+		accounts.append(&mut scanned);
+
+		// fetch and submit updated balances
+		match previous_key {
+			Some(key) => Self::fetch_and_submit_updated_balances(accounts, key)?,
+			None => Self::fetch_and_submit(&accounts, None)?,
+		}
+
+		log::info!(
+			target: "offchain-worker",
+			"Submitted updated balances for {} queued and {} scanned accounts",
+			queued_count,
+			scanned_count,
+		);
+
+		Ok(())
+	}
+
+	/// Sync balances changed since the change cursor of this node
+	///
+	/// Without a cursor only the current cursor of the processor is stored, earlier changes are
+	/// left to the full scan. So are changes of transactions which are not included.
+	fn sync_changes() -> Result<(), &'static str> {
+		if !Signer::<T, T::AuthorityId>::all_accounts().can_sign() {
+			return Err("No local accounts available");
+		}
+
+		let at = frame_system::Pallet::<T>::block_number();
+		let cursor = Self::change_cursor();
+
+		let (LedgerChanges { balances, cursor: next }, endpoint) =
+			Self::fetch_with_failover(at, |url| {
+				T::LedgerSource::fetch_changes(url, cursor.as_deref(), at)
+			})?;
+
+		let changed = balances.accounts.len();
+		Self::submit_balances(balances, at, endpoint, None)?;
+
+		StorageValueRef::persistent(CHANGE_CURSOR_KEY).set(&next);
+
+		log::info!(target: "offchain-worker", "Synced {} changed balances", changed);
+
+		Ok(())
+	}
+
 	/// Submit updated balances
 	fn fetch_and_submit_updated_balances(
 		accounts: Vec<AccountIdOf<T>>,
 		last_iterated_storage_key: Vec<u8>,
 	) -> Result<(), &'static str> {
-		// submit updated balances
-		let signer = Signer::<T, T::AuthorityId>::all_accounts();
+		let last_iterated_storage_key: StorageKey =
+			last_iterated_storage_key.try_into().map_err(|_| "Invalid key")?;
+
+		Self::fetch_and_submit(&accounts, Some(last_iterated_storage_key))
+	}
 
-		if !signer.can_sign() {
+	/// Fetch and submit balances of `accounts`
+	fn fetch_and_submit(
+		accounts: &[AccountIdOf<T>],
+		last_key: Option<StorageKey>,
+	) -> Result<(), &'static str> {
+		if !Signer::<T, T::AuthorityId>::all_accounts().can_sign() {
 			return Err("No local accounts available");
 		}
 
-		let mut updated_accounts =
-			Self::fetch_balances(&signer, accounts).map_err(|_| "Failed to fetch balances")?;
+		let at = frame_system::Pallet::<T>::block_number();
 
-		let last_iterated_storage_key: StorageKey =
-			last_iterated_storage_key.try_into().map_err(|_| "Invalid key")?;
+		let (balances, endpoint) = Self::fetch_with_failover(at, |url| {
+			T::LedgerSource::fetch_balances(url, accounts, at)
+		})?;
 
-		// check for each balance if it is updated
-		updated_accounts.retain(|(account, balance)| {
-			let current_balance = T::Currency::free_balance(account);
-			*balance != current_balance
-		});
+		Self::submit_balances(balances, at, endpoint, last_key)
+	}
+
+	/// Submit balances fetched in block `at` which differ from the on-chain balances
+	fn submit_balances(
+		balances: LedgerBalancesOf<T>,
+		at: BlockNumberFor<T>,
+		endpoint: Option<u32>,
+		last_key: Option<StorageKey>,
+	) -> Result<(), &'static str> {
+		let LedgerBalances { accounts: mut updated_accounts, signature } = balances;
+
+		if !Self::verify_processor_signature(&updated_accounts, at, signature.as_ref()) {
+			log::error!(target: "offchain-worker", "Invalid payment processor signature");
+			return Err("Invalid payment processor signature");
+		}
 
-		// only submit if there are updated balances
-		// we trust that API will only return updated balances
-		if updated_accounts.len().is_zero() {
+		// only submit if there are updated balances of registered accounts, queued accounts are
+		// submitted anyway to leave the queue
+		let queue = DirtyAccounts::<T>::get();
+		let outdated = |(account, balance): &(AccountIdOf<T>, BalanceOf<T>)| {
+			Accounts::<T>::contains_key(account) &&
+				(queue.iter().any(|(queued, _)| queued == account) ||
+					Self::free_balance_for(account, *balance) !=
+						T::Currency::free_balance(account))
+		};
+		if !updated_accounts.iter().any(outdated) {
 			return Ok(());
 		}
 
+		// signed balances are submitted as a whole, otherwise the signature can not be verified
+		if signature.is_none() {
+			updated_accounts.retain(|account| outdated(account));
+		}
+
 		// Actually send the extrinsic to the chain
-		let result = signer.send_unsigned_transaction(
-			|account| UpdateAccountsPayload {
-				public: account.public.clone(),
-				accounts: updated_accounts.clone(),
-				last_key: last_iterated_storage_key.clone(),
-			},
-			|payload, signature| Call::update_accounts_unsigned { payload, signature },
-		);
+		let signer = Signer::<T, T::AuthorityId>::all_accounts();
+		let result = match T::BalanceUpdateMode::get() {
+			SubmissionMode::Unsigned => signer.send_unsigned_transaction(
+				|account| UpdateAccountsPayload {
+					public: account.public.clone(),
+					accounts: updated_accounts.clone(),
+					last_key: last_key.clone(),
+					at,
+					processor_signature: signature.clone(),
+					endpoint,
+				},
+				|payload, signature| Call::update_accounts_unsigned { payload, signature },
+			),
+			SubmissionMode::Signed | SubmissionMode::SignedFeeless => signer
+				.send_signed_transaction(|_account| Call::update_accounts {
+					accounts: updated_accounts.clone(),
+					last_key: last_key.clone(),
+					at,
+					processor_signature: signature.clone(),
+					endpoint,
+				}),
+		};
 
 		for (acc, res) in &result {
 			match res {
@@ -679,112 +2265,63 @@ impl<T: Config> Pallet<T> {
 			}
 		}
 
+		if !result.iter().any(|(_, res)| res.is_ok()) {
+			return Err("Failed to submit updated balances");
+		}
+
 		Ok(())
 	}
 
-	/// Fetch balances of batch accounts
-	fn fetch_balances(
-		signer: &Signer<T, T::AuthorityId, ForAll>,
-		accounts: Vec<AccountIdOf<T>>,
-	) -> Result<AccountsOf<T>, http::Error> {
-		let deadline =
-			sp_io::offchain::timestamp().add(sp_core::offchain::Duration::from_millis(2_000));
-
-		// Body of the POST request, list of accounts
-		let body = JsonValue::Array(
-			accounts
-				.iter()
-				.map(|account| JsonValue::String(hex::encode(account.encode()).chars().collect()))
-				.collect::<Vec<_>>(),
-		);
-
-		#[cfg(not(test))]
-		// sign the body of the request
-		let results = signer.sign_message(&body.serialize());
-		#[cfg(not(test))]
-		let signature = results[0].1.encode();
-
-		// sr25519 signatures are non-deterministic
-		#[cfg(test)]
-		let signature = MOCKED_SIGNATURE.to_vec();
-
-		let body = JsonValue::Object(vec![
-			("accounts".chars().collect(), body),
-			(
-				"signature".chars().collect(),
-				JsonValue::String(hex::encode(&signature[..]).chars().collect()),
-			),
-		])
-		.serialize();
-
-		let url_base = PaymentProcessorUrl::<T>::get();
-
-		let mut url = url_base.into_inner();
-		url.push(b'/');
-		url.extend_from_slice(b"balances");
+	/// Relay the next `MaxBatchSize` pending requests and attest their outcome
+	///
+	/// Requests are read from the offchain index, the node must run with offchain indexing
+	/// enabled. Requests whose outcome this node submitted are not relayed again.
+	fn relay_transfers() -> Result<(), &'static str> {
+		let relayed_requests = StorageValueRef::persistent(RELAYED_REQUESTS_KEY);
+		let mut relayed = relayed_requests.get::<Vec<Hash>>().ok().flatten().unwrap_or_default();
+
+		// settled requests are not pending anymore
+		relayed.retain(|hash| PendingRequests::<T>::contains_key(hash));
+
+		let requests = PendingRequests::<T>::iter_keys()
+			.filter(|hash| !relayed.contains(hash))
+			.filter_map(|hash| {
+				let request = Self::transfer_request(&hash);
+				if request.is_none() {
+					log::warn!(target: "offchain-worker", "Request {:?} is not indexed", hash);
+				}
 
-		let url_str = core::str::from_utf8(&url).map_err(|_| http::Error::IoError)?;
+				request.map(|request| (hash, request))
+			})
+			.take(T::MaxBatchSize::get() as usize)
+			.collect::<Vec<_>>();
 
-		// Form the request
-		let request = http::Request::new(url_str)
-			.method(http::Method::Post)
-			.deadline(deadline)
-			.body(vec![body])
-			.add_header("Content-Type", "application/json")
-			.add_header("accept", "*/*")
-			.send()
-			.map_err(|_| http::Error::IoError)?;
+		if requests.is_empty() {
+			relayed_requests.set(&relayed);
+			return Ok(());
+		}
 
-		// Wait until the request is done, or until the deadline is reached
-		let response = request.try_wait(deadline).map_err(|_| http::Error::DeadlineReached)??;
+		let at = frame_system::Pallet::<T>::block_number();
+		let (transactions, _) =
+			Self::fetch_with_failover(at, |url| T::TransferRelay::relay(url, &requests, at))?;
 
-		let binding = response.body().collect::<Vec<u8>>();
+		let signer = Signer::<T, T::AuthorityId>::all_accounts();
+		for transaction in transactions {
+			let hash = transaction.hash;
+			let result = signer.send_signed_transaction(|_account| Call::submit_finality {
+				transaction: transaction.clone(),
+			});
 
-		let json_str: &str = match core::str::from_utf8(&binding) {
-			Ok(v) => v,
-			Err(_e) => "Error parsing json",
-		};
+			if result.iter().any(|(_, res)| res.is_ok()) {
+				log::info!(target: "offchain-worker", "Relayed transaction {:?}", hash);
+				relayed.push(hash);
+			} else {
+				log::error!(target: "offchain-worker", "Failed to attest transaction {:?}", hash);
+			}
+		}
 
-		let raw_accounts = parse_json(json_str).map_err(|_| http::Error::IoError)?;
-
-		let mut parsed_accounts = Vec::new();
-
-		// Parse the response. Expects a list of accounts and their balances
-		// Example response:
-		// ```json
-		// [
-		//   {"account_id": "5GQ...","balance": "100.11"},
-		//   {"account_id": "5FQ...","balance": "200.22"},
-		//   ..
-		// ]
-		// ```
-		match raw_accounts {
-			JsonValue::Array(inner_accounts) =>
-				for inner_account in inner_accounts {
-					match inner_account {
-						JsonValue::Object(entries) => {
-							debug_assert!(
-								entries.len() == 2,
-								"Invalid response, expected 2 fields"
-							);
-
-							let account_id = entries[0].clone();
-							let balance = entries[1].clone();
-
-							let account_id = AccountIdDecoder::<T>::try_convert(&account_id.1)
-								.map_err(|_| http::Error::IoError)?;
-
-							let balance = BalanceDecoder::<T>::try_convert(&balance.1)
-								.map_err(|_| http::Error::IoError)?;
-
-							parsed_accounts.push((account_id, balance));
-						},
-						_ => return Err(http::Error::IoError),
-					}
-				},
-			_ => return Err(http::Error::IoError),
-		};
+		relayed_requests.set(&relayed);
 
-		parsed_accounts.try_into().map_err(|_| http::Error::IoError)
+		Ok(())
 	}
 }
@@ -0,0 +1,235 @@
+//! Storage migrations for the ISO-8583 pallet.
+//!
+//! Each module migrates storage from the previous version and is a no-op if the on-chain storage
+//! version does not match the one it expects. Migrations are meant to be listed in the runtime's
+//! `Executive` in ascending order.
+
+use super::*;
+use frame_support::traits::{GetStorageVersion, OnRuntimeUpgrade};
+
+#[cfg(feature = "try-runtime")]
+use sp_runtime::TryRuntimeError;
+
+/// Log target of the migrations.
+const LOG_TARGET: &str = "runtime::iso-8583";
+
+/// Migration to `v1`: `()` values of `Accounts` and `OracleAccounts` become structured records.
+pub mod v1 {
+	use super::*;
+
+	/// Translates `()` values of `Accounts` and `OracleAccounts` to `AccountInfo` and
+	/// `OracleInfo` records.
+	///
+	/// Registration block of existing accounts is not known, so the block in which the migration
+	/// runs is recorded instead. Funds reserved by registered accounts are recorded in
+	/// `PendingReserves`, since transfers were the only reason for reserves before `v1`.
+	pub struct MigrateToV1<T>(sp_std::marker::PhantomData<T>);
+
+	impl<T: Config> OnRuntimeUpgrade for MigrateToV1<T> {
+		fn on_runtime_upgrade() -> Weight {
+			let on_chain_version = Pallet::<T>::on_chain_storage_version();
+
+			if on_chain_version != 0 {
+				log::info!(
+					target: LOG_TARGET,
+					"Skipping migration to v1, on-chain storage version is {:?}",
+					on_chain_version,
+				);
+				return T::DbWeight::get().reads(1);
+			}
+
+			let now = frame_system::Pallet::<T>::block_number();
+			let mut translated = 0u64;
+
+			Accounts::<T>::translate::<(), _>(|account, ()| {
+				translated.saturating_inc();
+
+				let reserved = T::Currency::reserved_balance(&account);
+				if !reserved.is_zero() {
+					PendingReserves::<T>::insert(&account, reserved);
+				}
+
+				Some(AccountInfo::new(now))
+			});
+
+			OracleAccounts::<T>::translate::<(), _>(|_, ()| {
+				translated.saturating_inc();
+				Some(OracleInfo::new(now))
+			});
+
+			StorageVersion::new(1).put::<Pallet<T>>();
+
+			log::info!(target: LOG_TARGET, "Migrated {} entries to v1", translated);
+
+			T::DbWeight::get().reads_writes(2 * translated + 1, 2 * translated + 1)
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn pre_upgrade() -> Result<Vec<u8>, TryRuntimeError> {
+			let accounts = Accounts::<T>::iter_keys().count() as u32;
+			let oracles = OracleAccounts::<T>::iter_keys().count() as u32;
+
+			Ok((accounts, oracles).encode())
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade(state: Vec<u8>) -> Result<(), TryRuntimeError> {
+			let (accounts, oracles): (u32, u32) =
+				Decode::decode(&mut &state[..]).map_err(|_| "Invalid pre-upgrade state")?;
+
+			ensure!(Pallet::<T>::on_chain_storage_version() >= 1, "Storage version not updated");
+
+			// every value must decode as a record, `iter_values` skips the ones that don't
+			ensure!(
+				Accounts::<T>::iter_values().count() as u32 == accounts,
+				"Not all accounts were migrated"
+			);
+			ensure!(
+				OracleAccounts::<T>::iter_values().count() as u32 == oracles,
+				"Not all oracle accounts were migrated"
+			);
+
+			Ok(())
+		}
+	}
+}
+
+/// Migration to `v2`: the single `PaymentProcessorUrl` becomes the list `ProcessorEndpoints`.
+pub mod v2 {
+	use super::*;
+
+	/// Payment processor url before `v2`
+	#[frame_support::storage_alias]
+	pub type PaymentProcessorUrl<T: Config> = StorageValue<Pallet<T>, StorageKey, ValueQuery>;
+
+	/// Moves `PaymentProcessorUrl` to the primary of `ProcessorEndpoints`.
+	///
+	/// An empty url results in an empty list.
+	pub struct MigrateToV2<T>(sp_std::marker::PhantomData<T>);
+
+	impl<T: Config> OnRuntimeUpgrade for MigrateToV2<T> {
+		fn on_runtime_upgrade() -> Weight {
+			let on_chain_version = Pallet::<T>::on_chain_storage_version();
+
+			if on_chain_version != 1 {
+				log::info!(
+					target: LOG_TARGET,
+					"Skipping migration to v2, on-chain storage version is {:?}",
+					on_chain_version,
+				);
+				return T::DbWeight::get().reads(1);
+			}
+
+			let url = PaymentProcessorUrl::<T>::take();
+
+			if !url.is_empty() {
+				let endpoints: BoundedVec<StorageKey, T::MaxEndpoints> =
+					sp_std::vec![url].try_into().expect("`MaxEndpoints` is at least 1");
+				ProcessorEndpoints::<T>::put(endpoints);
+			}
+
+			StorageVersion::new(2).put::<Pallet<T>>();
+
+			log::info!(target: LOG_TARGET, "Migrated payment processor url to v2");
+
+			T::DbWeight::get().reads_writes(2, 3)
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn pre_upgrade() -> Result<Vec<u8>, TryRuntimeError> {
+			Ok(PaymentProcessorUrl::<T>::get().encode())
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade(state: Vec<u8>) -> Result<(), TryRuntimeError> {
+			let url: StorageKey =
+				Decode::decode(&mut &state[..]).map_err(|_| "Invalid pre-upgrade state")?;
+
+			ensure!(Pallet::<T>::on_chain_storage_version() >= 2, "Storage version not updated");
+			ensure!(!PaymentProcessorUrl::<T>::exists(), "Payment processor url not removed");
+			ensure!(
+				url.is_empty() || ProcessorEndpoints::<T>::get().first() == Some(&url),
+				"Payment processor url not migrated"
+			);
+
+			Ok(())
+		}
+	}
+}
+
+/// Migration to `v3`: accounts in `DirtyAccounts` record the block they were queued in.
+pub mod v3 {
+	use super::*;
+
+	/// Sync queue before `v3`
+	type Queue<T> = BoundedVec<AccountIdOf<T>, <T as Config>::MaxDirtyAccounts>;
+
+	/// Records the block in which the migration runs as the block queued accounts were queued in.
+	///
+	/// A queue that does not decode is dropped, the scan of `Accounts` reaches its accounts.
+	pub struct MigrateToV3<T>(sp_std::marker::PhantomData<T>);
+
+	impl<T: Config> OnRuntimeUpgrade for MigrateToV3<T> {
+		fn on_runtime_upgrade() -> Weight {
+			let on_chain_version = Pallet::<T>::on_chain_storage_version();
+
+			if on_chain_version != 2 {
+				log::info!(
+					target: LOG_TARGET,
+					"Skipping migration to v3, on-chain storage version is {:?}",
+					on_chain_version,
+				);
+				return T::DbWeight::get().reads(1);
+			}
+
+			let now = frame_system::Pallet::<T>::block_number();
+
+			let translated = DirtyAccounts::<T>::translate::<Queue<T>, _>(|queue| {
+				queue.map(|queue| {
+					BoundedVec::truncate_from(
+						queue.into_iter().map(|account| (account, now)).collect::<Vec<_>>(),
+					)
+				})
+			});
+			let queued = match translated {
+				Ok(queue) => queue.map_or(0, |queue| queue.len()),
+				Err(_) => {
+					DirtyAccounts::<T>::kill();
+					0
+				},
+			};
+
+			StorageVersion::new(3).put::<Pallet<T>>();
+
+			log::info!(target: LOG_TARGET, "Migrated {} queued accounts to v3", queued);
+
+			T::DbWeight::get().reads_writes(2, 2)
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn pre_upgrade() -> Result<Vec<u8>, TryRuntimeError> {
+			let queued = if Pallet::<T>::on_chain_storage_version() == 2 {
+				frame_support::storage::unhashed::get::<Queue<T>>(&DirtyAccounts::<T>::hashed_key())
+					.map_or(0, |queue| queue.len() as u32)
+			} else {
+				DirtyAccounts::<T>::get().len() as u32
+			};
+
+			Ok(queued.encode())
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade(state: Vec<u8>) -> Result<(), TryRuntimeError> {
+			let queued: u32 =
+				Decode::decode(&mut &state[..]).map_err(|_| "Invalid pre-upgrade state")?;
+
+			ensure!(Pallet::<T>::on_chain_storage_version() >= 3, "Storage version not updated");
+			ensure!(
+				DirtyAccounts::<T>::get().len() as u32 == queued,
+				"Not all queued accounts were migrated"
+			);
+
+			Ok(())
+		}
+	}
+}
@@ -1,17 +1,41 @@
 //! Mock runtime for tests
 
-use crate::crypto;
+use crate::{
+	crypto,
+	ledger::{
+		HttpJsonLedger, Iso8583Ledger, LedgerBalances, LedgerBalancesOf, LedgerChanges,
+		LedgerChangesOf, LedgerError, LedgerSource, RequestSigner, TransferRelay,
+	},
+	types::{
+		FinalisedTransaction, FinalisedTransactionOf, Hash, ISO8583Status, RequestKind,
+		TransferRequestOf, UpdateAccountsPayload,
+	},
+	AccountsOf, LedgerView, ProcessorSignature, StorageKey, SubmissionMode,
+};
+use codec::Decode;
 use frame_support::{parameter_types, traits::Everything, PalletId};
+use frame_system::EnsureRoot;
 use pallet_balances::AccountData;
-use sp_core::{sr25519::Signature, ConstU128, ConstU32, ConstU64, Pair, Public, H256};
+use parking_lot::RwLock;
+use sp_core::{
+	offchain::{
+		testing::{PoolState, TestOffchainExt, TestTransactionPoolExt},
+		OffchainDbExt, OffchainWorkerExt, TransactionPoolExt,
+	},
+	sr25519::Signature,
+	ConstU128, ConstU32, ConstU64, Pair, Public, H256,
+};
+use sp_keystore::{testing::MemoryKeystore, Keystore, KeystoreExt};
 use sp_runtime::{
+	offchain::http::Error as HttpError,
 	testing::TestXt,
 	traits::{
 		AccountIdConversion, BlakeTwo256, Extrinsic as ExtrinsicT, IdentifyAccount, IdentityLookup,
 		Verify,
 	},
-	BuildStorage,
+	BuildStorage, RuntimeAppPublic,
 };
+use std::sync::Arc;
 
 type Block = frame_system::mocking::MockBlock<Test>;
 type Balance = u128;
@@ -19,6 +43,18 @@ type Balance = u128;
 /// Initial balance of an account.
 pub(crate) const INITIAL_BALANCE: Balance = 100;
 
+/// Seed phrase of the offchain worker key
+pub(crate) const PHRASE: &str =
+	"news slush supreme milk chapter athlete soap sausage put clutch what kitten";
+
+/// Signature of `MockSigner`
+pub(crate) const MOCKED_SIGNATURE: [u8; 64] = [
+	192, 93, 98, 222, 3, 215, 244, 47, 53, 196, 78, 14, 232, 48, 38, 87, 243, 210, 18, 249, 38,
+	135, 182, 239, 29, 12, 204, 246, 126, 242, 148, 113, 155, 92, 146, 117, 165, 156, 244, 91, 46,
+	62, 224, 153, 45, 78, 121, 173, 214, 20, 54, 72, 187, 41, 77, 29, 103, 241, 44, 5, 238, 171, 5,
+	138,
+];
+
 frame_support::construct_runtime!(
 	pub enum Test
 	{
@@ -80,9 +116,148 @@ impl pallet_timestamp::Config for Test {
 
 parameter_types! {
 	pub PalletAccount: AccountId = PalletId(*b"py/iso85").into_account_truncating();
+	pub static FinalityThreshold: u32 = 1;
+	pub const OracleBond: Balance = 10;
+	/// Response of `MockLedger`, the payment processor is queried if `None`
+	pub static LedgerResponse: Option<Result<Vec<(AccountId, Balance)>, LedgerError>> = None;
+	/// Processor signature attached to `LedgerResponse`
+	pub static LedgerSignature: Option<ProcessorSignature> = None;
+	/// Endpoints `MockLedger` times out on
+	pub static UnavailableEndpoints: Vec<Vec<u8>> = vec![];
+	/// Endpoints queried by `MockLedger`, in order
+	pub static QueriedEndpoints: Vec<Vec<u8>> = vec![];
+	/// Changed balances and change cursor of `MockLedger`, the payment processor is queried if
+	/// `None`
+	pub static ChangesResponse: Option<Result<(Vec<(AccountId, Balance)>, Vec<u8>), LedgerError>> =
+		None;
+	/// Change cursors queried by `MockLedger`, in order
+	pub static QueriedCursors: Vec<Option<Vec<u8>>> = vec![];
+	/// Every run scans `Accounts` unless set
+	pub static ReconciliationInterval: u64 = 1;
+	/// Accepted balance refresh requests are free unless unset
+	pub static FeelessRefresh: bool = true;
+	/// Balances are available balances unless set
+	pub static BalanceView: LedgerView = LedgerView::Available;
+	/// Balance updates are unsigned unless set
+	pub static BalanceUpdateMode: SubmissionMode = SubmissionMode::Unsigned;
+	/// Status `MockRelay` answers transfers with, the payment processor is queried if `None`
+	pub static RelayStatus: Option<ISO8583Status> = None;
+	/// Requests relayed by `MockRelay`, in order
+	pub static RelayedRequests: Vec<Hash> = vec![];
+}
+
+/// Signer double, sr25519 signatures of the keystore are not deterministic
+///
+/// Signs every message with `MOCKED_SIGNATURE`.
+pub struct MockSigner;
+
+impl RequestSigner<Test> for MockSigner {
+	fn sign(_message: &[u8]) -> Result<Vec<u8>, LedgerError> {
+		Ok(MOCKED_SIGNATURE.to_vec())
+	}
+}
+
+/// Ledger double, serves `LedgerResponse` if set and queries the payment processor otherwise
+///
+/// Endpoints in `UnavailableEndpoints` time out. Changes are served from `ChangesResponse`
+/// likewise.
+pub struct MockLedger;
+
+impl LedgerSource<Test> for MockLedger {
+	const CHANGE_FEED: bool = true;
+
+	fn fetch_balances(
+		url: &[u8],
+		accounts: &[AccountId],
+		at: u64,
+	) -> Result<LedgerBalancesOf<Test>, LedgerError> {
+		QueriedEndpoints::mutate(|queried| queried.push(url.to_vec()));
+
+		if UnavailableEndpoints::get().iter().any(|endpoint| endpoint == url) {
+			return Err(LedgerError::Http(HttpError::DeadlineReached));
+		}
+
+		match LedgerResponse::get() {
+			Some(response) => Ok(LedgerBalances {
+				accounts: response?
+					.into_iter()
+					.filter(|(account, _)| accounts.contains(account))
+					.collect::<Vec<_>>()
+					.try_into()
+					.map_err(|_| LedgerError::TooManyAccounts)?,
+				signature: LedgerSignature::get(),
+			}),
+			None => HttpJsonLedger::<Test, MockSigner>::fetch_balances(url, accounts, at),
+		}
+	}
+
+	fn fetch_changes(
+		url: &[u8],
+		cursor: Option<&[u8]>,
+		at: u64,
+	) -> Result<LedgerChangesOf<Test>, LedgerError> {
+		QueriedEndpoints::mutate(|queried| queried.push(url.to_vec()));
+		QueriedCursors::mutate(|queried| queried.push(cursor.map(|cursor| cursor.to_vec())));
+
+		if UnavailableEndpoints::get().iter().any(|endpoint| endpoint == url) {
+			return Err(LedgerError::Http(HttpError::DeadlineReached));
+		}
+
+		match ChangesResponse::get() {
+			Some(response) => {
+				let (accounts, cursor) = response?;
+				Ok(LedgerChanges {
+					balances: LedgerBalances {
+						accounts: accounts.try_into().map_err(|_| LedgerError::TooManyAccounts)?,
+						signature: LedgerSignature::get(),
+					},
+					cursor,
+				})
+			},
+			None => HttpJsonLedger::<Test, MockSigner>::fetch_changes(url, cursor, at),
+		}
+	}
+}
+
+/// Relay double, answers transfers with `RelayStatus` if set and queries the payment processor
+/// otherwise
+///
+/// Reverts are left unanswered.
+pub struct MockRelay;
+
+impl TransferRelay<Test> for MockRelay {
+	fn relay(
+		url: &[u8],
+		requests: &[(Hash, TransferRequestOf<Test>)],
+		at: u64,
+	) -> Result<Vec<FinalisedTransactionOf<Test>>, LedgerError> {
+		RelayedRequests::mutate(|relayed| relayed.extend(requests.iter().map(|(hash, _)| *hash)));
+
+		let status = match RelayStatus::get() {
+			Some(status) => status,
+			None => return Iso8583Ledger::<Test>::relay(url, requests, at),
+		};
+
+		Ok(requests
+			.iter()
+			.filter_map(|(hash, request)| match &request.kind {
+				RequestKind::Transfer { from, to, amount } => Some(FinalisedTransaction {
+					hash: *hash,
+					from: *from,
+					to: *to,
+					amount: *amount,
+					event_id: request.event_id.clone(),
+					status: status.clone(),
+				}),
+				RequestKind::Revert { .. } => None,
+			})
+			.collect())
+	}
 }
 
 pub(crate) type Extrinsic = TestXt<RuntimeCall, ()>;
+/// Payload of `update_accounts_unsigned`
+pub(crate) type Payload = UpdateAccountsPayload<AccountId, AccountsOf<Test>, StorageKey, u64>;
 pub(crate) type AccountId = <<Signature as Verify>::Signer as IdentifyAccount>::AccountId;
 
 impl frame_system::offchain::SigningTypes for Test {
@@ -115,10 +290,38 @@ where
 impl crate::Config for Test {
 	type AuthorityId = crypto::Iso8583AuthId;
 	type RuntimeEvent = RuntimeEvent;
+	type LedgerSource = MockLedger;
+	type TransferRelay = MockRelay;
 	type Currency = Balances;
 	type PalletAccount = PalletAccount;
 	type MaxStringSize = ConstU32<1024>;
 	type OffchainWorkerInterval = ConstU64<2>;
+	type MaxBatchSize = ConstU32<20>;
+	type ReconciliationInterval = ReconciliationInterval;
+	type MaxDirtyAccounts = ConstU32<3>;
+	type DirtyAccountTimeout = ConstU64<10>;
+	type RefreshCooldown = ConstU64<5>;
+	type FeelessRefresh = FeelessRefresh;
+	type DriftAlertThreshold = ConstU128<20>;
+	type BalanceView = BalanceView;
+	type BalanceUpdateMode = BalanceUpdateMode;
+	type OffchainRequestDeadline = ConstU64<2_000>;
+	type MaxSyncRetries = ConstU32<3>;
+	type SyncPauseDuration = ConstU64<10>;
+	type MaxEndpoints = ConstU32<3>;
+	type EndpointProbeInterval = ConstU64<10>;
+	type FinalityThreshold = FinalityThreshold;
+	type MaxOracles = ConstU32<16>;
+	type OracleBond = OracleBond;
+	type UnbondingPeriod = ConstU64<5>;
+	type HeartbeatTimeout = ConstU64<10>;
+	type SettlementTimeout = ConstU64<5>;
+	type RetentionPeriod = ConstU64<20>;
+	type MaxPrunedPerBlock = ConstU32<4>;
+	type StatsWindowLength = ConstU64<10>;
+	type MaxStatsWindows = ConstU32<3>;
+	type AdminOrigin = EnsureRoot<AccountId>;
+	type OracleOrigin = crate::EnsureOracle<Test>;
 }
 
 /// Mock account id for testing
@@ -164,14 +367,6 @@ impl ExtBuilder {
 	pub(crate) fn build(self) -> sp_io::TestExternalities {
 		let mut t = frame_system::GenesisConfig::<Test>::default().build_storage().unwrap();
 
-		crate::GenesisConfig::<Test> {
-			oracle_accounts: self.oracle_accounts.clone(),
-			accounts: self.accounts.clone(),
-			payment_processor_url: b"http://localhost:3001".to_vec(),
-		}
-		.assimilate_storage(&mut t)
-		.unwrap();
-
 		let mut endowed_accounts = self.accounts.clone();
 
 		endowed_accounts.append(&mut self.oracle_accounts.clone());
@@ -182,6 +377,56 @@ impl ExtBuilder {
 		.assimilate_storage(&mut t)
 		.unwrap();
 
+		// oracles reserve their bond from the endowment
+		crate::GenesisConfig::<Test> {
+			oracle_accounts: self.oracle_accounts.clone(),
+			accounts: self.accounts.clone(),
+			processor_endpoints: vec![b"http://localhost:3001".to_vec()],
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
 		t.into()
 	}
+
+	/// Build test externalities for the offchain worker
+	///
+	/// The offchain worker key is in the keystore and the offchain database is the one offchain
+	/// indexing writes to, returns the state of the transaction pool as well.
+	pub(crate) fn build_offchain(self) -> (sp_io::TestExternalities, Arc<RwLock<PoolState>>) {
+		let mut t = self.build();
+		let (offchain, _state) = TestOffchainExt::with_offchain_db(t.offchain_db());
+		let (pool, pool_state) = TestTransactionPoolExt::new();
+		let keystore = MemoryKeystore::new();
+		keystore
+			.sr25519_generate_new(crypto::Public::ID, Some(&format!("{}/iso8583", PHRASE)))
+			.unwrap();
+
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+		t.register_extension(TransactionPoolExt::new(pool));
+		t.register_extension(KeystoreExt::new(keystore));
+
+		(t, pool_state)
+	}
+
+	/// Build test externalities, execute `test` and check the invariants of the pallet
+	pub(crate) fn build_and_execute(self, test: impl FnOnce()) {
+		self.build().execute_with(|| {
+			test();
+			ISO8583::do_try_state().expect("invariants hold after the test");
+		})
+	}
+}
+
+/// Payload of the last balance update submitted to the transaction pool
+pub(crate) fn submitted_payload(pool_state: &RwLock<PoolState>) -> Option<Payload> {
+	let tx = pool_state.write().transactions.pop()?;
+	let tx = Extrinsic::decode(&mut &tx[..]).unwrap();
+
+	match tx.call {
+		RuntimeCall::ISO8583(crate::Call::update_accounts_unsigned { payload, .. }) =>
+			Some(payload),
+		_ => panic!("unexpected call"),
+	}
 }
@@ -6,15 +6,6 @@ use frame_support::{assert_noop, assert_ok};
 use sp_core::H256;
 use sp_runtime::DispatchError;
 
-const PHRASE: &str = "news slush supreme milk chapter athlete soap sausage put clutch what kitten";
-/// Mocked signature
-pub(crate) const MOCKED_SIGNATURE: [u8; 64] = [
-	192, 93, 98, 222, 3, 215, 244, 47, 53, 196, 78, 14, 232, 48, 38, 87, 243, 210, 18, 249, 38,
-	135, 182, 239, 29, 12, 204, 246, 126, 242, 148, 113, 155, 92, 146, 117, 165, 156, 244, 91, 46,
-	62, 224, 153, 45, 78, 121, 173, 214, 20, 54, 72, 187, 41, 77, 29, 103, 241, 44, 5, 238, 171, 5,
-	138,
-];
-
 mod extrinsics {
 	use frame_system::offchain::SigningTypes;
 	use sp_keystore::{testing::MemoryKeystore, Keystore, KeystoreExt};
@@ -26,7 +17,7 @@ mod extrinsics {
 
 	#[test]
 	fn test_register() {
-		ExtBuilder::default().with_oracle_accounts(vec![1]).build().execute_with(|| {
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
 			// only oracle can register
 			assert_noop!(
 				ISO8583::register(RuntimeOrigin::signed(account(255)), account(1), 100),
@@ -43,8 +34,7 @@ mod extrinsics {
 		ExtBuilder::default()
 			.with_oracle_accounts(vec![1, 2])
 			.with_accounts(vec![3, 4, 5])
-			.build()
-			.execute_with(|| {
+			.build_and_execute(|| {
 				// set block to 1, to read events
 				System::set_block_number(1);
 
@@ -97,8 +87,7 @@ mod extrinsics {
 		ExtBuilder::default()
 			.with_oracle_accounts(vec![1, 2])
 			.with_accounts(vec![3, 4, 5])
-			.build()
-			.execute_with(|| {
+			.build_and_execute(|| {
 				// set block to 1, to read events
 				System::set_block_number(1);
 
@@ -153,8 +142,7 @@ mod extrinsics {
 		ExtBuilder::default()
 			.with_oracle_accounts(vec![1, 2])
 			.with_accounts(vec![3, 4, 5])
-			.build()
-			.execute_with(|| {
+			.build_and_execute(|| {
 				// set block to 1, to read events
 				System::set_block_number(1);
 
@@ -166,14 +154,27 @@ mod extrinsics {
 					20
 				));
 
-				let dummy_hash = H256::from([0; 32]);
+				let hash = crate::Transfers::<Test>::iter_keys().next().unwrap();
+
+				// settle transfer
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					FinalisedTransaction {
+						from: account(3),
+						to: account(4),
+						amount: 20,
+						hash,
+						event_id: (1_u32, 0_u32).encode().try_into().unwrap(),
+						status: crate::types::ISO8583Status::Approved,
+					}
+				));
 
 				// initiate reversal
-				assert_ok!(ISO8583::initiate_revert(RuntimeOrigin::signed(account(1)), dummy_hash));
+				assert_ok!(ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), hash));
 
 				// event is emitted
 				System::assert_has_event(RuntimeEvent::ISO8583(
-					crate::Event::<Test>::InitiateRevert { who: account(1), hash: dummy_hash },
+					crate::Event::<Test>::InitiateRevert { who: account(3), hash },
 				));
 			});
 	}
@@ -183,8 +184,7 @@ mod extrinsics {
 		ExtBuilder::default()
 			.with_oracle_accounts(vec![1, 2])
 			.with_accounts(vec![3, 4, 5])
-			.build()
-			.execute_with(|| {
+			.build_and_execute(|| {
 				// set block to 1, to read events
 				System::set_block_number(1);
 
@@ -246,7 +246,7 @@ mod extrinsics {
 					from: account(3),
 					to: account(5),
 					amount: 23,
-					hash: H256::from([0; 32]),
+					hash: H256::from([1; 32]),
 					event_id: (2_u32, 0_u32).encode().try_into().unwrap(),
 					status: crate::types::ISO8583Status::Approved,
 				};
@@ -283,7 +283,7 @@ mod extrinsics {
 
 	#[test]
 	fn test_remove_works() {
-		ExtBuilder::default().with_oracle_accounts(vec![1]).build().execute_with(|| {
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
 			// set block to 1, to read events
 			System::set_block_number(1);
 
@@ -323,7 +323,10 @@ mod extrinsics {
 					accounts: vec![(account(123), 100_110_000), (account(125), 125_250_000)]
 						.try_into()
 						.unwrap(),
-					last_key: vec![].try_into().unwrap(),
+					last_key: Some(vec![].try_into().unwrap()),
+					at: 0,
+					processor_signature: None,
+					endpoint: None,
 				},
 				<Test as SigningTypes>::Signature::decode(&mut MOCKED_SIGNATURE.as_slice())
 					.unwrap(),
@@ -333,7 +336,7 @@ mod extrinsics {
 
 	#[test]
 	fn test_register_oracle_works() {
-		ExtBuilder::default().with_oracle_accounts(vec![1]).build().execute_with(|| {
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
 			// set block to 1, to read events
 			System::set_block_number(1);
 
@@ -350,7 +353,7 @@ mod extrinsics {
 
 	#[test]
 	fn test_remove_oracle() {
-		ExtBuilder::default().with_oracle_accounts(vec![1]).build().execute_with(|| {
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
 			// set block to 1, to read events
 			System::set_block_number(1);
 
@@ -367,6 +370,25 @@ mod extrinsics {
 			assert!(!<OracleAccounts<Test>>::contains_key(account(1)));
 		});
 	}
+
+	#[test]
+	fn ensure_oracle_works() {
+		use frame_support::traits::EnsureOrigin;
+
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
+			type OracleOrigin = <Test as crate::Config>::OracleOrigin;
+
+			// resolves to the oracle account
+			assert_eq!(
+				OracleOrigin::try_origin(RuntimeOrigin::signed(account(1))).ok(),
+				Some(account(1))
+			);
+
+			// neither other accounts nor root are oracles
+			assert!(OracleOrigin::try_origin(RuntimeOrigin::signed(account(2))).is_err());
+			assert!(OracleOrigin::try_origin(RuntimeOrigin::root()).is_err());
+		});
+	}
 }
 
 mod trait_tests {
@@ -378,7 +400,7 @@ mod trait_tests {
 
 	#[test]
 	fn test_transfer_works() {
-		ExtBuilder::default().with_accounts(vec![3, 4]).build().execute_with(|| {
+		ExtBuilder::default().with_accounts(vec![3, 4]).build_and_execute(|| {
 			// set block to 1, to read events
 			System::set_block_number(1);
 
@@ -403,7 +425,7 @@ mod trait_tests {
 
 	#[test]
 	fn test_approve_works() {
-		ExtBuilder::default().with_accounts(vec![3, 4]).build().execute_with(|| {
+		ExtBuilder::default().with_accounts(vec![3, 4]).build_and_execute(|| {
 			// set block to 1, to read events
 			System::set_block_number(1);
 
@@ -430,7 +452,7 @@ mod trait_tests {
 
 	#[test]
 	fn test_transfer_from_works() {
-		ExtBuilder::default().with_accounts(vec![3, 4, 5]).build().execute_with(|| {
+		ExtBuilder::default().with_accounts(vec![3, 4, 5]).build_and_execute(|| {
 			// set block to 1, to read events
 			System::set_block_number(1);
 
@@ -472,12 +494,17 @@ mod trait_tests {
 
 mod offchain_worker {
 	use super::*;
-	use crate::{AccountsOf, Config};
+	use crate::{
+		ledger::{HttpJsonLedger, Iso8583Ledger, LedgerBalances, LedgerError, LedgerSource},
+		AccountsOf, Config, PAYMENT_PROCESSOR_URL_KEY,
+	};
 	use codec::Decode;
 	use frame_support::traits::{Get, OffchainWorker};
-	use frame_system::{offchain::Signer, pallet_prelude::BlockNumberFor};
+	use frame_system::pallet_prelude::BlockNumberFor;
 	use lite_json::{JsonValue, NumberValue, Serialize};
-	use sp_core::offchain::{testing, OffchainWorkerExt, TransactionPoolExt};
+	use sp_core::offchain::{
+		testing, OffchainDbExt, OffchainWorkerExt, StorageKind, TransactionPoolExt,
+	};
 	use sp_keystore::{testing::MemoryKeystore, Keystore, KeystoreExt};
 	use sp_runtime::RuntimeAppPublic;
 
@@ -512,6 +539,7 @@ mod offchain_worker {
 	fn fetch_balances_works() {
 		let (offchain, state) = testing::TestOffchainExt::new();
 		let mut t = ExtBuilder::default().with_accounts(vec![]).build();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
 		t.register_extension(OffchainWorkerExt::new(offchain));
 
 		let keystore = MemoryKeystore::new();
@@ -521,7 +549,6 @@ mod offchain_worker {
 		t.register_extension(KeystoreExt::new(keystore));
 
 		let interval: BlockNumberFor<Test> = <Test as Config>::OffchainWorkerInterval::get();
-		let signer = Signer::<Test, <Test as crate::Config>::AuthorityId>::all_accounts();
 
 		// we are not expecting any request
 		t.execute_with(|| {
@@ -537,14 +564,14 @@ mod offchain_worker {
 				98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98,
 				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55,
 				98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 34, 93, 44, 34,
-				115, 105, 103, 110, 97, 116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54, 50,
-				100, 101, 48, 51, 100, 55, 102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101, 101,
-				56, 51, 48, 50, 54, 53, 55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55, 98,
-				54, 101, 102, 49, 100, 48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55, 49,
-				57, 98, 53, 99, 57, 50, 55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51, 101,
-				101, 48, 57, 57, 50, 100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54, 52,
-				56, 98, 98, 50, 57, 52, 100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101, 101,
-				97, 98, 48, 53, 56, 97, 34, 125,
+				97, 116, 34, 58, 48, 44, 34, 115, 105, 103, 110, 97, 116, 117, 114, 101, 34, 58,
+				34, 99, 48, 53, 100, 54, 50, 100, 101, 48, 51, 100, 55, 102, 52, 50, 102, 51, 53,
+				99, 52, 52, 101, 48, 101, 101, 56, 51, 48, 50, 54, 53, 55, 102, 51, 100, 50, 49,
+				50, 102, 57, 50, 54, 56, 55, 98, 54, 101, 102, 49, 100, 48, 99, 99, 99, 102, 54,
+				55, 101, 102, 50, 57, 52, 55, 49, 57, 98, 53, 99, 57, 50, 55, 53, 97, 53, 57, 99,
+				102, 52, 53, 98, 50, 101, 51, 101, 101, 48, 57, 57, 50, 100, 52, 101, 55, 57, 97,
+				100, 100, 54, 49, 52, 51, 54, 52, 56, 98, 98, 50, 57, 52, 100, 49, 100, 54, 55,
+				102, 49, 50, 99, 48, 53, 101, 101, 97, 98, 48, 53, 56, 97, 34, 125,
 			];
 
 			let response = mock_response(vec![(123, 100.11)]);
@@ -569,8 +596,13 @@ mod offchain_worker {
 			let parsed_accounts: AccountsOf<Test> =
 				vec![(account(123), 100110000000000)].try_into().unwrap();
 			assert_eq!(
-				ISO8583::fetch_balances(&signer, vec![account(123)]).unwrap(),
-				parsed_accounts
+				HttpJsonLedger::<Test, MockSigner>::fetch_balances(
+					b"http://localhost:3001",
+					&[account(123)],
+					0
+				)
+				.unwrap(),
+				LedgerBalances { accounts: parsed_accounts, signature: None }
 			);
 		});
 	}
@@ -585,6 +617,7 @@ mod offchain_worker {
 			.unwrap();
 
 		let mut t = ExtBuilder::default().with_accounts(vec![123, 125]).build();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
 		t.register_extension(OffchainWorkerExt::new(offchain));
 		t.register_extension(TransactionPoolExt::new(pool));
 		t.register_extension(KeystoreExt::new(keystore));
@@ -601,15 +634,15 @@ mod offchain_worker {
 				100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55,
 				100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55,
 				100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55, 100, 55,
-				100, 55, 100, 55, 100, 55, 100, 55, 100, 34, 93, 44, 34, 115, 105, 103, 110, 97,
-				116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54, 50, 100, 101, 48, 51, 100, 55,
-				102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101, 101, 56, 51, 48, 50, 54, 53,
-				55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55, 98, 54, 101, 102, 49, 100,
-				48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55, 49, 57, 98, 53, 99, 57, 50,
-				55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51, 101, 101, 48, 57, 57, 50,
-				100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54, 52, 56, 98, 98, 50, 57, 52,
-				100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101, 101, 97, 98, 48, 53, 56, 97,
-				34, 125,
+				100, 55, 100, 55, 100, 55, 100, 55, 100, 34, 93, 44, 34, 97, 116, 34, 58, 48, 44,
+				34, 115, 105, 103, 110, 97, 116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54,
+				50, 100, 101, 48, 51, 100, 55, 102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101,
+				101, 56, 51, 48, 50, 54, 53, 55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55,
+				98, 54, 101, 102, 49, 100, 48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55,
+				49, 57, 98, 53, 99, 57, 50, 55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51,
+				101, 101, 48, 57, 57, 50, 100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54,
+				52, 56, 98, 98, 50, 57, 52, 100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101,
+				101, 97, 98, 48, 53, 56, 97, 34, 125,
 			];
 
 			let response = mock_response(vec![(123, 100.11), (125, 125.25)]);
@@ -670,6 +703,7 @@ mod offchain_worker {
 
 		let mut t = ExtBuilder::default().with_accounts(vec![123, 125]).build();
 
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
 		t.register_extension(OffchainWorkerExt::new(offchain));
 		t.register_extension(TransactionPoolExt::new(pool));
 		t.register_extension(KeystoreExt::new(keystore));
@@ -688,15 +722,15 @@ mod offchain_worker {
 				100, 55, 100, 55, 100, 34, 44, 34, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98,
 				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55,
 				98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98,
-				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 34, 93, 44, 34, 115, 105, 103, 110, 97,
-				116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54, 50, 100, 101, 48, 51, 100, 55,
-				102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101, 101, 56, 51, 48, 50, 54, 53,
-				55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55, 98, 54, 101, 102, 49, 100,
-				48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55, 49, 57, 98, 53, 99, 57, 50,
-				55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51, 101, 101, 48, 57, 57, 50,
-				100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54, 52, 56, 98, 98, 50, 57, 52,
-				100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101, 101, 97, 98, 48, 53, 56, 97,
-				34, 125,
+				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 34, 93, 44, 34, 97, 116, 34, 58, 48, 44,
+				34, 115, 105, 103, 110, 97, 116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54,
+				50, 100, 101, 48, 51, 100, 55, 102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101,
+				101, 56, 51, 48, 50, 54, 53, 55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55,
+				98, 54, 101, 102, 49, 100, 48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55,
+				49, 57, 98, 53, 99, 57, 50, 55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51,
+				101, 101, 48, 57, 57, 50, 100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54,
+				52, 56, 98, 98, 50, 57, 52, 100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101,
+				101, 97, 98, 48, 53, 56, 97, 34, 125,
 			];
 
 			let response = mock_response(vec![(125, 125.25), (123, 100.11)]);
@@ -752,15 +786,15 @@ mod offchain_worker {
 				100, 55, 100, 55, 100, 34, 44, 34, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98,
 				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55,
 				98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 55, 98,
-				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 34, 93, 44, 34, 115, 105, 103, 110, 97,
-				116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54, 50, 100, 101, 48, 51, 100, 55,
-				102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101, 101, 56, 51, 48, 50, 54, 53,
-				55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55, 98, 54, 101, 102, 49, 100,
-				48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55, 49, 57, 98, 53, 99, 57, 50,
-				55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51, 101, 101, 48, 57, 57, 50,
-				100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54, 52, 56, 98, 98, 50, 57, 52,
-				100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101, 101, 97, 98, 48, 53, 56, 97,
-				34, 125,
+				55, 98, 55, 98, 55, 98, 55, 98, 55, 98, 34, 93, 44, 34, 97, 116, 34, 58, 48, 44,
+				34, 115, 105, 103, 110, 97, 116, 117, 114, 101, 34, 58, 34, 99, 48, 53, 100, 54,
+				50, 100, 101, 48, 51, 100, 55, 102, 52, 50, 102, 51, 53, 99, 52, 52, 101, 48, 101,
+				101, 56, 51, 48, 50, 54, 53, 55, 102, 51, 100, 50, 49, 50, 102, 57, 50, 54, 56, 55,
+				98, 54, 101, 102, 49, 100, 48, 99, 99, 99, 102, 54, 55, 101, 102, 50, 57, 52, 55,
+				49, 57, 98, 53, 99, 57, 50, 55, 53, 97, 53, 57, 99, 102, 52, 53, 98, 50, 101, 51,
+				101, 101, 48, 57, 57, 50, 100, 52, 101, 55, 57, 97, 100, 100, 54, 49, 52, 51, 54,
+				52, 56, 98, 98, 50, 57, 52, 100, 49, 100, 54, 55, 102, 49, 50, 99, 48, 53, 101,
+				101, 97, 98, 48, 53, 56, 97, 34, 125,
 			];
 
 			let response = mock_response(vec![]);
@@ -786,4 +820,2749 @@ mod offchain_worker {
 			assert!(pool_state.read().transactions.is_empty());
 		});
 	}
+
+	#[test]
+	fn iso8583_balance_inquiry_works() {
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let mut t = ExtBuilder::default().with_accounts(vec![123, 125]).build();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+
+		// length prefixed ASCII messages
+		let frame = |messages: Vec<String>| {
+			messages
+				.into_iter()
+				.flat_map(|message| {
+					let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+					framed.extend(message.into_bytes());
+					framed
+				})
+				.collect::<Vec<u8>>()
+		};
+
+		let request = |stan: &str, account: &str| {
+			format!("01002020000000010000310000{}064{}", stan, account.repeat(32))
+		};
+
+		{
+			let mut state = state.write();
+
+			let response = frame(vec![
+				// approved, 100.11 available
+				format!(
+					"01102020000002010400310000000000{}064{}0200002840C000000010011",
+					"00",
+					"7b".repeat(32)
+				),
+				// unknown account
+				format!("01102020000002010000310000000001{}064{}", "14", "7d".repeat(32)),
+			]);
+
+			state.expect_request(testing::PendingRequest {
+				method: "POST".into(),
+				uri: "http://localhost:3001/iso8583".into(),
+				body: frame(vec![request("000000", "7b"), request("000001", "7d")]),
+				response: Some(response),
+				sent: true,
+				headers: vec![("Content-Type".to_string(), "application/octet-stream".to_string())],
+				..Default::default()
+			});
+		}
+
+		t.execute_with(|| {
+			let expected: AccountsOf<Test> = vec![(account(123), 100_110_000)].try_into().unwrap();
+
+			assert_eq!(
+				Iso8583Ledger::<Test>::fetch_balances(
+					b"http://localhost:3001",
+					&[account(123), account(125)],
+					0
+				),
+				Ok(LedgerBalances { accounts: expected, signature: None })
+			);
+		});
+	}
+
+	#[test]
+	fn paginated_balances_are_followed() {
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let mut t = ExtBuilder::default().with_accounts(vec![123, 125]).build();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+
+		let keystore = MemoryKeystore::new();
+		keystore
+			.sr25519_generate_new(crate::crypto::Public::ID, Some(&format!("{}/iso8583", PHRASE)))
+			.unwrap();
+		t.register_extension(KeystoreExt::new(keystore));
+
+		let request = |cursor: &str| {
+			format!(
+				r#"{{"accounts":["{}","{}"],"at":0,"signature":"{}"{}}}"#,
+				hex::encode(account(123).encode()),
+				hex::encode(account(125).encode()),
+				hex::encode(MOCKED_SIGNATURE),
+				cursor
+			)
+			.into_bytes()
+		};
+
+		{
+			let mut state = state.write();
+
+			for (body, response) in [
+				(
+					request(""),
+					format!(
+						r#"{{"accounts":[{{"balance":"100.11","accountId":"{}"}}],"next":"c2"}}"#,
+						hex::encode(account(123).encode())
+					),
+				),
+				(
+					request(r#","cursor":"c2""#),
+					format!(
+						r#"{{"accounts":[{{"accountId":"{}","balance":125.25}}],"next":null}}"#,
+						hex::encode(account(125).encode())
+					),
+				),
+			] {
+				state.expect_request(testing::PendingRequest {
+					method: "POST".into(),
+					uri: "http://localhost:3001/balances".into(),
+					body,
+					response: Some(response.into_bytes()),
+					sent: true,
+					headers: vec![
+						("Content-Type".to_string(), "application/json".to_string()),
+						("accept".to_string(), "*/*".to_string()),
+					],
+					..Default::default()
+				});
+			}
+		}
+
+		t.execute_with(|| {
+			let expected: AccountsOf<Test> =
+				vec![(account(123), 100_110_000), (account(125), 125_250_000)]
+					.try_into()
+					.unwrap();
+
+			assert_eq!(
+				HttpJsonLedger::<Test, MockSigner>::fetch_balances(
+					b"http://localhost:3001",
+					&[account(123), account(125)],
+					0
+				),
+				Ok(LedgerBalances { accounts: expected, signature: None })
+			);
+		});
+	}
+
+	#[test]
+	fn local_processor_url_takes_precedence() {
+		let (offchain, _state) = testing::TestOffchainExt::new();
+		let mut t = ExtBuilder::default().build();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+
+		t.execute_with(|| {
+			let on_chain = (Some(0), b"http://localhost:3001".to_vec());
+			assert_eq!(ISO8583::endpoints(), vec![on_chain.clone()]);
+
+			sp_io::offchain::local_storage_set(
+				StorageKind::PERSISTENT,
+				PAYMENT_PROCESSOR_URL_KEY,
+				b"http://10.0.0.7:3001",
+			);
+			assert_eq!(
+				ISO8583::endpoints(),
+				vec![(None, b"http://10.0.0.7:3001".to_vec()), on_chain.clone()]
+			);
+
+			// an empty url falls back to the on-chain one
+			sp_io::offchain::local_storage_set(
+				StorageKind::PERSISTENT,
+				PAYMENT_PROCESSOR_URL_KEY,
+				b"",
+			);
+			assert_eq!(ISO8583::endpoints(), vec![on_chain]);
+		});
+	}
+
+	#[test]
+	fn balances_come_from_ledger_source() {
+		let (mut t, pool_state) =
+			ExtBuilder::default().with_accounts(vec![123, 125]).build_offchain();
+
+		t.execute_with(|| {
+			// failing source submits nothing
+			LedgerResponse::set(Some(Err(LedgerError::InvalidResponse)));
+			assert!(ISO8583::fetch_and_submit_updated_balances(
+				vec![account(123), account(125)],
+				vec![]
+			)
+			.is_err());
+			assert!(pool_state.read().transactions.is_empty());
+
+			// unchanged balances are not submitted
+			LedgerResponse::set(Some(Ok(vec![
+				(account(123), 42),
+				(account(125), INITIAL_BALANCE),
+			])));
+			assert_ok!(ISO8583::fetch_and_submit_updated_balances(
+				vec![account(123), account(125)],
+				vec![]
+			));
+
+			let tx = pool_state.write().transactions.pop().unwrap();
+			let tx = crate::mock::Extrinsic::decode(&mut &tx[..]).unwrap();
+
+			match tx.call {
+				RuntimeCall::ISO8583(crate::Call::update_accounts_unsigned { payload, .. }) => {
+					let expected_accounts: AccountsOf<Test> =
+						vec![(account(123), 42)].try_into().unwrap();
+					assert_eq!(payload.accounts, expected_accounts);
+				},
+				_ => panic!("unexpected call"),
+			}
+		});
+	}
+}
+
+mod sync_state {
+	use super::*;
+	use crate::{types::SyncState, SYNC_LOCK_KEY};
+	use frame_support::traits::OffchainWorker;
+	use sp_runtime::offchain::{
+		storage_lock::{StorageLock, Time},
+		Duration,
+	};
+
+	/// Externalities and the number of submitted transactions
+	fn build() -> (sp_io::TestExternalities, impl Fn() -> usize) {
+		let (t, pool_state) = ExtBuilder::default().with_accounts(vec![123, 125]).build_offchain();
+
+		(t, move || pool_state.read().transactions.len())
+	}
+
+	#[test]
+	fn failed_runs_are_retried_then_paused() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			LedgerResponse::set(Some(Err(LedgerError::InvalidResponse)));
+
+			// first failure is retried in the next block
+			ISO8583::offchain_worker(2);
+			assert_eq!(
+				ISO8583::sync_state(),
+				SyncState { failures: 1, retry_at: Some(3), paused_until: None }
+			);
+
+			// then with exponential backoff
+			ISO8583::offchain_worker(3);
+			assert_eq!(
+				ISO8583::sync_state(),
+				SyncState { failures: 2, retry_at: Some(5), paused_until: None }
+			);
+
+			// `MaxSyncRetries` failures pause syncing for `SyncPauseDuration`
+			ISO8583::offchain_worker(5);
+			assert_eq!(
+				ISO8583::sync_state(),
+				SyncState { failures: 0, retry_at: None, paused_until: Some(15) }
+			);
+
+			// paused runs do not query the ledger
+			LedgerResponse::set(Some(Ok(vec![(account(123), 42)])));
+			ISO8583::offchain_worker(14);
+			assert_eq!(submitted(), 0);
+
+			// successful run resets the state
+			ISO8583::offchain_worker(16);
+			assert_eq!(ISO8583::sync_state(), SyncState::default());
+			assert_eq!(submitted(), 1);
+		});
+	}
+
+	#[test]
+	fn overlapping_runs_are_skipped() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			LedgerResponse::set(Some(Ok(vec![(account(123), 42)])));
+
+			// a previous run still holds the lock
+			let mut lock =
+				StorageLock::<Time>::with_deadline(SYNC_LOCK_KEY, Duration::from_millis(60_000));
+			let guard = lock.try_lock().unwrap();
+
+			ISO8583::offchain_worker(2);
+			assert_eq!(submitted(), 0);
+
+			drop(guard);
+
+			ISO8583::offchain_worker(2);
+			assert_eq!(submitted(), 1);
+		});
+	}
+}
+
+mod failover {
+	use super::*;
+	use crate::{
+		types::{FailoverState, UpdateAccountsPayload},
+		ActiveEndpoint, ProcessorEndpoints,
+	};
+	use frame_support::{traits::ConstU32, BoundedVec};
+
+	pub(super) fn endpoints(urls: &[&str]) -> BoundedVec<crate::StorageKey, ConstU32<3>> {
+		urls.iter()
+			.map(|url| url.as_bytes().to_vec().try_into().unwrap())
+			.collect::<Vec<_>>()
+			.try_into()
+			.unwrap()
+	}
+
+	fn build() -> sp_io::TestExternalities {
+		ExtBuilder::default().with_accounts(vec![123]).build_offchain().0
+	}
+
+	/// Fetch balances of account `123`, returns the queried endpoints
+	fn fetch() -> (Result<(), &'static str>, Vec<Vec<u8>>) {
+		QueriedEndpoints::set(vec![]);
+		let result = ISO8583::fetch_and_submit_updated_balances(vec![account(123)], vec![]);
+
+		(result, QueriedEndpoints::get())
+	}
+
+	#[test]
+	fn admin_sets_endpoints() {
+		ExtBuilder::default().build_and_execute(|| {
+			System::set_block_number(1);
+			ActiveEndpoint::<Test>::put(0);
+
+			assert_noop!(
+				ISO8583::set_processor_endpoints(
+					RuntimeOrigin::signed(account(1)),
+					endpoints(&["http://a"])
+				),
+				DispatchError::BadOrigin
+			);
+
+			assert_ok!(ISO8583::set_processor_endpoints(
+				RuntimeOrigin::root(),
+				endpoints(&["http://a", "http://b"])
+			));
+			System::assert_last_event(RuntimeEvent::ISO8583(
+				crate::Event::<Test>::ProcessorEndpointsChanged {
+					endpoints: endpoints(&["http://a", "http://b"]),
+				},
+			));
+
+			assert_eq!(ActiveEndpoint::<Test>::get(), None);
+			assert_eq!(
+				ISO8583::processor_endpoints_status(),
+				vec![(b"http://a".to_vec(), false), (b"http://b".to_vec(), false)]
+			);
+		});
+	}
+
+	#[test]
+	fn unavailable_endpoints_are_failed_over() {
+		build().execute_with(|| {
+			ProcessorEndpoints::<Test>::put(endpoints(&["http://a", "http://b", "http://c"]));
+			LedgerResponse::set(Some(Ok(vec![(account(123), 42)])));
+			UnavailableEndpoints::set(vec![b"http://a".to_vec()]);
+
+			let (result, queried) = fetch();
+			assert_ok!(result);
+			assert_eq!(queried, vec![b"http://a".to_vec(), b"http://b".to_vec()]);
+			assert_eq!(
+				ISO8583::failover_state(),
+				FailoverState { active: 1, failures: vec![1, 0, 0], probed_at: Some(0) }
+			);
+
+			// the endpoint in use is kept
+			let (_, queried) = fetch();
+			assert_eq!(queried, vec![b"http://b".to_vec()]);
+
+			// primary is probed after `EndpointProbeInterval`
+			System::set_block_number(10);
+			let (_, queried) = fetch();
+			assert_eq!(queried, vec![b"http://a".to_vec(), b"http://b".to_vec()]);
+			assert_eq!(
+				ISO8583::failover_state(),
+				FailoverState { active: 1, failures: vec![2, 0, 0], probed_at: Some(10) }
+			);
+
+			// and used again once it recovers
+			UnavailableEndpoints::set(vec![]);
+			System::set_block_number(20);
+			let (_, queried) = fetch();
+			assert_eq!(queried, vec![b"http://a".to_vec()]);
+			assert_eq!(
+				ISO8583::failover_state(),
+				FailoverState { active: 0, failures: vec![0, 0, 0], probed_at: None }
+			);
+
+			// all endpoints unavailable
+			UnavailableEndpoints::set(vec![
+				b"http://a".to_vec(),
+				b"http://b".to_vec(),
+				b"http://c".to_vec(),
+			]);
+			let (result, queried) = fetch();
+			assert_eq!(result, Err("All payment processor endpoints are unavailable"));
+			assert_eq!(queried.len(), 3);
+		});
+	}
+
+	#[test]
+	fn malformed_responses_are_not_failed_over() {
+		build().execute_with(|| {
+			ProcessorEndpoints::<Test>::put(endpoints(&["http://a", "http://b"]));
+			LedgerResponse::set(Some(Err(LedgerError::InvalidJson)));
+
+			let (result, queried) = fetch();
+			assert_eq!(result, Err("Failed to fetch balances"));
+			assert_eq!(queried, vec![b"http://a".to_vec()]);
+			assert_eq!(ISO8583::failover_state().active, 0);
+		});
+	}
+
+	#[test]
+	fn serving_endpoint_is_recorded() {
+		ExtBuilder::default().with_accounts(vec![123]).build_and_execute(|| {
+			System::set_block_number(1);
+			ProcessorEndpoints::<Test>::put(endpoints(&["http://a", "http://b"]));
+
+			let update = |endpoint| {
+				ISO8583::update_accounts_unsigned(
+					RuntimeOrigin::none(),
+					UpdateAccountsPayload {
+						public: account(1),
+						accounts: vec![(account(123), 42)].try_into().unwrap(),
+						last_key: Some(vec![].try_into().unwrap()),
+						at: 1,
+						processor_signature: None,
+						endpoint,
+					},
+					<Test as frame_system::offchain::SigningTypes>::Signature::decode(
+						&mut MOCKED_SIGNATURE.as_slice(),
+					)
+					.unwrap(),
+				)
+			};
+
+			assert_ok!(update(Some(1)));
+			System::assert_last_event(RuntimeEvent::ISO8583(
+				crate::Event::<Test>::ActiveEndpointChanged { endpoint: 1 },
+			));
+			assert_eq!(
+				ISO8583::processor_endpoints_status(),
+				vec![(b"http://a".to_vec(), false), (b"http://b".to_vec(), true)]
+			);
+
+			// the url local to a node does not change the active endpoint
+			assert_ok!(update(None));
+			assert_eq!(ActiveEndpoint::<Test>::get(), Some(1));
+		});
+	}
+}
+
+mod delta_sync {
+	use super::*;
+	use crate::{
+		types::{AccountInfo, AccountsOf, UpdateAccountsPayload},
+		LastIteratedStorageKey,
+	};
+	use frame_support::traits::OffchainWorker;
+
+	/// Externalities and the payload of the last submitted transaction
+	fn build() -> (sp_io::TestExternalities, impl Fn() -> Option<Payload>) {
+		let (t, pool_state) = ExtBuilder::default().with_accounts(vec![123, 125]).build_offchain();
+
+		ReconciliationInterval::set(10);
+
+		(t, move || submitted_payload(&pool_state))
+	}
+
+	#[test]
+	fn changes_are_synced_between_reconciliations() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			// without a cursor only the cursor of the processor is stored
+			ChangesResponse::set(Some(Ok((vec![], b"c1".to_vec()))));
+			ISO8583::offchain_worker(2);
+			assert_eq!(ISO8583::change_cursor(), Some(b"c1".to_vec()));
+			assert_eq!(submitted(), None);
+
+			// changes of unregistered accounts are left out
+			ChangesResponse::set(Some(Ok((
+				vec![(account(123), 42), (account(7), 1)],
+				b"c2".to_vec(),
+			))));
+			ISO8583::offchain_worker(4);
+			assert_eq!(QueriedCursors::get(), vec![None, Some(b"c1".to_vec())]);
+			assert_eq!(ISO8583::change_cursor(), Some(b"c2".to_vec()));
+
+			let payload = submitted().unwrap();
+			let expected_accounts: AccountsOf<Test> = vec![(account(123), 42)].try_into().unwrap();
+			assert_eq!(payload.accounts, expected_accounts);
+			assert_eq!(payload.last_key, None);
+
+			// every `ReconciliationInterval` blocks accounts are scanned
+			LedgerResponse::set(Some(Ok(vec![(account(125), 7)])));
+			ISO8583::offchain_worker(10);
+			assert_eq!(QueriedCursors::get().len(), 2);
+
+			let payload = submitted().unwrap();
+			let expected_accounts: AccountsOf<Test> = vec![(account(125), 7)].try_into().unwrap();
+			assert_eq!(payload.accounts, expected_accounts);
+			assert!(payload.last_key.is_some());
+		});
+	}
+
+	#[test]
+	fn failed_changes_keep_the_cursor() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			ChangesResponse::set(Some(Ok((vec![], b"c1".to_vec()))));
+			ISO8583::offchain_worker(2);
+
+			ChangesResponse::set(Some(Err(LedgerError::InvalidJson)));
+			ISO8583::offchain_worker(4);
+			assert_eq!(ISO8583::change_cursor(), Some(b"c1".to_vec()));
+			assert_eq!(ISO8583::sync_state().failures, 1);
+
+			// the retry asks for the same changes again
+			ChangesResponse::set(Some(Ok((vec![(account(123), 42)], b"c2".to_vec()))));
+			ISO8583::offchain_worker(5);
+			assert_eq!(
+				QueriedCursors::get(),
+				vec![None, Some(b"c1".to_vec()), Some(b"c1".to_vec())]
+			);
+			assert_eq!(ISO8583::change_cursor(), Some(b"c2".to_vec()));
+			assert!(submitted().is_some());
+		});
+	}
+
+	#[test]
+	fn changes_leave_the_scan_where_it_is() {
+		ExtBuilder::default().with_accounts(vec![123, 125]).build_and_execute(|| {
+			let key = crate::Accounts::<Test>::hashed_key_for(account(123));
+			LastIteratedStorageKey::<Test>::put(crate::StorageKey::try_from(key.clone()).unwrap());
+
+			assert_ok!(ISO8583::update_accounts_unsigned(
+				RuntimeOrigin::none(),
+				UpdateAccountsPayload {
+					public: account(1),
+					accounts: vec![(account(125), 42)].try_into().unwrap(),
+					last_key: None,
+					at: 0,
+					processor_signature: None,
+					endpoint: None,
+				},
+				<Test as frame_system::offchain::SigningTypes>::Signature::decode(
+					&mut MOCKED_SIGNATURE.as_slice(),
+				)
+				.unwrap(),
+			));
+
+			assert_eq!(Balances::free_balance(account(125)), 42);
+			assert_eq!(LastIteratedStorageKey::<Test>::get().unwrap().into_inner(), key);
+		});
+	}
+
+	#[test]
+	fn accounts_are_scanned_in_batches() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			for value in 1..=23 {
+				crate::Accounts::<Test>::insert(account(value), AccountInfo::new(0));
+			}
+			LedgerResponse::set(Some(Ok((1..=125).map(|value| (account(value), 42)).collect())));
+
+			// the first `MaxBatchSize` accounts continue from their last key
+			ISO8583::offchain_worker(10);
+			let payload = submitted().unwrap();
+			assert_eq!(payload.accounts.len(), 20);
+
+			let last_key = payload.last_key.clone().unwrap().into_inner();
+			assert_eq!(&last_key[..32], &ISO8583::accounts_prefix()[..]);
+			assert!(last_key.len() > 32);
+			assert_ok!(super::dirty_accounts::update(payload));
+
+			// the remaining accounts start the scan over
+			ISO8583::offchain_worker(20);
+			let payload = submitted().unwrap();
+			assert_eq!(payload.accounts.len(), 5);
+			assert_eq!(payload.last_key.clone().unwrap().into_inner(), ISO8583::accounts_prefix());
+			assert_ok!(super::dirty_accounts::update(payload));
+
+			assert!(crate::Accounts::<Test>::iter_keys()
+				.all(|account| Balances::free_balance(account) == 42));
+		});
+	}
+}
+
+mod dirty_accounts {
+	use super::*;
+	use crate::{
+		types::{AccountsOf, ISO8583Status, UpdateAccountsPayload},
+		DirtyAccounts,
+	};
+	use frame_support::traits::OffchainWorker;
+
+	/// Externalities and the payload of the last submitted transaction
+	fn build() -> (sp_io::TestExternalities, impl Fn() -> Option<Payload>) {
+		let (t, pool_state) = ExtBuilder::default().with_accounts(vec![123, 125]).build_offchain();
+
+		(t, move || submitted_payload(&pool_state))
+	}
+
+	/// Accounts in `DirtyAccounts`
+	pub(super) fn queued() -> Vec<AccountId> {
+		ISO8583::dirty_accounts().into_iter().map(|(account, _)| account).collect()
+	}
+
+	pub(super) fn update(payload: Payload) -> sp_runtime::DispatchResult {
+		ISO8583::update_accounts_unsigned(
+			RuntimeOrigin::none(),
+			payload,
+			<Test as frame_system::offchain::SigningTypes>::Signature::decode(
+				&mut MOCKED_SIGNATURE.as_slice(),
+			)
+			.unwrap(),
+		)
+	}
+
+	#[test]
+	fn transfers_and_settlements_queue_accounts() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4, 5, 6])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				// unregistered accounts are not queued
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(10),
+					10
+				));
+				assert_eq!(queued(), vec![account(3)]);
+
+				// accounts are queued once
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(4),
+					10
+				));
+				assert_eq!(queued(), vec![account(3), account(4)]);
+
+				// settlement queues the accounts of the transaction
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					FinalisedTransaction {
+						from: <Test as crate::Config>::PalletAccount::get(),
+						to: account(5),
+						amount: 20,
+						hash: H256::from([1; 32]),
+						event_id: (1_u32, 0_u32).encode().try_into().unwrap(),
+						status: ISO8583Status::Approved,
+					}
+				));
+				assert_eq!(queued(), vec![account(3), account(4), account(5)]);
+
+				// accounts which do not fit are left to the scan
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(6)),
+					account(6),
+					account(3),
+					10
+				));
+				assert_eq!(ISO8583::dirty_accounts().len(), 3);
+
+				// removed and synced accounts leave the queue
+				assert_ok!(ISO8583::remove(RuntimeOrigin::signed(account(1)), account(4)));
+				assert_ok!(update(UpdateAccountsPayload {
+					public: account(1),
+					accounts: vec![(account(3), 42)].try_into().unwrap(),
+					last_key: None,
+					at: 1,
+					processor_signature: None,
+					endpoint: None,
+				}));
+				assert_eq!(queued(), vec![account(5)]);
+			});
+	}
+
+	#[test]
+	fn queued_accounts_are_synced_first() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			DirtyAccounts::<Test>::put(
+				frame_support::BoundedVec::try_from(vec![(account(125), 0)]).unwrap(),
+			);
+			LedgerResponse::set(Some(Ok(vec![
+				(account(123), 42),
+				(account(125), INITIAL_BALANCE),
+			])));
+
+			// unchanged balances of queued accounts are submitted to leave the queue, the scan
+			// continues with the rest of the batch
+			ISO8583::offchain_worker(2);
+			let payload = submitted().unwrap();
+			let expected_accounts: AccountsOf<Test> =
+				vec![(account(125), INITIAL_BALANCE), (account(123), 42)].try_into().unwrap();
+			assert_eq!(payload.accounts, expected_accounts);
+			assert!(payload.last_key.is_some());
+
+			assert_ok!(update(payload));
+			assert!(ISO8583::dirty_accounts().is_empty());
+		});
+	}
+
+	#[test]
+	fn queue_is_synced_on_every_run() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			// changes are synced between scans
+			ReconciliationInterval::set(10);
+			ChangesResponse::set(Some(Ok((vec![], b"1".to_vec()))));
+			LedgerResponse::set(Some(Ok(vec![(account(123), 42)])));
+
+			ISO8583::offchain_worker(2);
+			assert_eq!(submitted(), None);
+			assert_eq!(QueriedCursors::get().len(), 1);
+
+			// queued accounts are synced instead
+			System::set_block_number(3);
+			assert!(ISO8583::mark_dirty(&account(123)));
+			ISO8583::offchain_worker(4);
+			let payload = submitted().unwrap();
+			let expected_accounts: AccountsOf<Test> = vec![(account(123), 42)].try_into().unwrap();
+			assert_eq!(payload.accounts, expected_accounts);
+			assert_eq!(QueriedCursors::get().len(), 1);
+		});
+	}
+
+	#[test]
+	fn omitted_accounts_leave_the_queue() {
+		let (mut t, submitted) = build();
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			assert!(ISO8583::mark_dirty(&account(123)));
+			assert!(ISO8583::mark_dirty(&account(125)));
+
+			// the processor does not know account 125
+			LedgerResponse::set(Some(Ok(vec![(account(123), 42)])));
+
+			ISO8583::offchain_worker(2);
+			let payload = submitted().unwrap();
+			let expected_accounts: AccountsOf<Test> = vec![(account(123), 42)].try_into().unwrap();
+			assert_eq!(payload.accounts, expected_accounts);
+			assert_ok!(update(payload));
+			assert_eq!(queued(), vec![account(125)]);
+
+			// the queue does not hold up the scan
+			LedgerResponse::set(Some(Ok(vec![(account(123), 43)])));
+			ISO8583::offchain_worker(4);
+			let payload = submitted().unwrap();
+			let expected_accounts: AccountsOf<Test> = vec![(account(123), 43)].try_into().unwrap();
+			assert_eq!(payload.accounts, expected_accounts);
+			assert!(payload.last_key.is_some());
+
+			// nor does it keep accounts the processor did not return for `DirtyAccountTimeout`
+			System::set_block_number(11);
+			assert_ok!(update(payload));
+			assert!(queued().is_empty());
+		});
+	}
+}
+
+mod balance_refresh {
+	use super::*;
+	use crate::{
+		tests::dirty_accounts::{queued, update},
+		types::UpdateAccountsPayload,
+		DirtyAccounts,
+	};
+	use frame_support::{dispatch::Pays, BoundedVec};
+
+	fn synced(account_id: u8, balance: u128) -> Payload {
+		UpdateAccountsPayload {
+			public: account(1),
+			accounts: vec![(account(account_id), balance)].try_into().unwrap(),
+			last_key: None,
+			at: 0,
+			processor_signature: None,
+			endpoint: None,
+		}
+	}
+
+	#[test]
+	fn registered_accounts_request_refresh() {
+		ExtBuilder::default().with_accounts(vec![3]).build_and_execute(|| {
+			System::set_block_number(1);
+
+			assert_noop!(
+				ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(9))),
+				Error::<Test>::SourceNotRegistered
+			);
+
+			let info = ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(3))).unwrap();
+			assert_eq!(info.pays_fee, Pays::No);
+			System::assert_last_event(RuntimeEvent::ISO8583(
+				crate::Event::<Test>::BalanceRefreshRequested { account: account(3) },
+			));
+			assert_eq!(queued(), vec![account(3)]);
+
+			// requests are rate limited
+			System::set_block_number(5);
+			assert_noop!(
+				ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(3))),
+				Error::<Test>::RefreshTooSoon
+			);
+
+			// refreshed balance is reported once
+			assert_ok!(update(synced(3, 42)));
+			System::assert_last_event(RuntimeEvent::ISO8583(
+				crate::Event::<Test>::BalanceRefreshed { account: account(3), balance: 42 },
+			));
+			assert!(!ISO8583::refresh_requests(account(3)).unwrap().pending);
+
+			assert_ok!(update(synced(3, 43)));
+			let refreshed = System::events()
+				.into_iter()
+				.filter(|record| {
+					matches!(
+						record.event,
+						RuntimeEvent::ISO8583(crate::Event::<Test>::BalanceRefreshed { .. })
+					)
+				})
+				.count();
+			assert_eq!(refreshed, 1);
+
+			// fees can be charged
+			System::set_block_number(6);
+			FeelessRefresh::set(false);
+			let info = ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(3))).unwrap();
+			assert_eq!(info.pays_fee, Pays::Yes);
+		});
+	}
+
+	#[test]
+	fn refresh_times_out() {
+		ExtBuilder::default().with_accounts(vec![3, 4]).build_and_execute(|| {
+			System::set_block_number(1);
+			assert_ok!(ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(3))));
+
+			// the processor did not return the account within `DirtyAccountTimeout`
+			System::set_block_number(11);
+			assert_ok!(update(synced(4, 42)));
+			assert_eq!(queued(), vec![]);
+			System::assert_has_event(RuntimeEvent::ISO8583(
+				crate::Event::<Test>::BalanceRefreshTimedOut { account: account(3) },
+			));
+			assert!(!ISO8583::refresh_requests(account(3)).unwrap().pending);
+
+			// a later balance update is not reported as the refresh
+			assert_ok!(update(synced(3, 42)));
+			assert!(!System::events().into_iter().any(|record| {
+				matches!(
+					record.event,
+					RuntimeEvent::ISO8583(crate::Event::<Test>::BalanceRefreshed { .. })
+				)
+			}));
+
+			// the refresh can be requested again
+			assert_ok!(ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(3))));
+			assert_eq!(queued(), vec![account(3)]);
+		});
+	}
+
+	#[test]
+	fn refresh_needs_room_in_the_queue() {
+		ExtBuilder::default().with_accounts(vec![3, 4, 5, 6]).build_and_execute(|| {
+			DirtyAccounts::<Test>::put(
+				BoundedVec::try_from(vec![(account(3), 0), (account(4), 0), (account(5), 0)])
+					.unwrap(),
+			);
+
+			assert_noop!(
+				ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(6))),
+				Error::<Test>::RefreshQueueFull
+			);
+
+			// queued accounts can request it anyway
+			assert_ok!(ISO8583::request_balance_refresh(RuntimeOrigin::signed(account(3))));
+		});
+	}
+}
+
+mod balance_drift {
+	use super::*;
+	use crate::{tests::dirty_accounts::update, types::UpdateAccountsPayload};
+
+	#[test]
+	fn corrections_are_recorded() {
+		ExtBuilder::default().with_accounts(vec![3, 4, 5]).build_and_execute(|| {
+			System::set_block_number(1);
+
+			assert_ok!(update(UpdateAccountsPayload {
+				public: account(1),
+				accounts: vec![
+					(account(3), INITIAL_BALANCE + 42),
+					(account(4), INITIAL_BALANCE - 10),
+					(account(5), INITIAL_BALANCE),
+				]
+				.try_into()
+				.unwrap(),
+				last_key: None,
+				at: 0,
+				processor_signature: None,
+				endpoint: None,
+			}));
+
+			System::assert_has_event(RuntimeEvent::ISO8583(crate::Event::<Test>::BalanceSynced {
+				account: account(3),
+				old: INITIAL_BALANCE,
+				new: INITIAL_BALANCE + 42,
+			}));
+			System::assert_has_event(RuntimeEvent::ISO8583(crate::Event::<Test>::BalanceSynced {
+				account: account(4),
+				old: INITIAL_BALANCE,
+				new: INITIAL_BALANCE - 10,
+			}));
+
+			// only corrections above `DriftAlertThreshold` raise an alert
+			let alerts = System::events()
+				.into_iter()
+				.filter_map(|record| match record.event {
+					RuntimeEvent::ISO8583(crate::Event::<Test>::DriftAlert { account, .. }) =>
+						Some(account),
+					_ => None,
+				})
+				.collect::<Vec<_>>();
+			assert_eq!(alerts, vec![account(3)]);
+
+			// unchanged balances are not corrections
+			assert_eq!(
+				ISO8583::balance_drift(),
+				crate::types::DriftStats { credited: 42, debited: 10, corrections: 2 }
+			);
+		});
+	}
+}
+
+mod ledger_view {
+	use super::*;
+	use crate::{tests::dirty_accounts::update, types::UpdateAccountsPayload, LedgerView};
+
+	fn synced(balance: u128) -> sp_runtime::DispatchResult {
+		update(UpdateAccountsPayload {
+			public: account(1),
+			accounts: vec![(account(3), balance)].try_into().unwrap(),
+			last_key: None,
+			at: 0,
+			processor_signature: None,
+			endpoint: None,
+		})
+	}
+
+	#[test]
+	fn pending_transfers_are_kept_reserved() {
+		ExtBuilder::default().with_accounts(vec![3, 4]).build_and_execute(|| {
+			System::set_block_number(1);
+
+			assert_ok!(ISO8583::initiate_transfer(
+				RuntimeOrigin::signed(account(3)),
+				account(3),
+				account(4),
+				30
+			));
+
+			// ledger balance still includes the pending transfer
+			BalanceView::set(LedgerView::Ledger);
+			assert_ok!(synced(INITIAL_BALANCE));
+			assert_eq!(Balances::free_balance(account(3)), INITIAL_BALANCE - 30);
+			assert_eq!(Balances::reserved_balance(account(3)), 30);
+			assert_eq!(ISO8583::balance_drift().corrections, 0);
+
+			assert_ok!(synced(INITIAL_BALANCE - 10));
+			assert_eq!(Balances::free_balance(account(3)), INITIAL_BALANCE - 40);
+
+			// available balance already excludes it
+			BalanceView::set(LedgerView::Available);
+			assert_ok!(synced(INITIAL_BALANCE - 30));
+			assert_eq!(Balances::free_balance(account(3)), INITIAL_BALANCE - 30);
+			assert_eq!(Balances::reserved_balance(account(3)), 30);
+		});
+	}
+}
+
+mod signed_updates {
+	use super::*;
+	use crate::SubmissionMode;
+	use frame_support::{dispatch::Pays, traits::OffchainWorker};
+
+	fn update(
+		origin: RuntimeOrigin,
+		balances: Vec<(AccountId, u128)>,
+		at: u64,
+	) -> frame_support::dispatch::DispatchResultWithPostInfo {
+		ISO8583::update_accounts(origin, balances.try_into().unwrap(), None, at, None, None)
+	}
+
+	#[test]
+	fn oracles_submit_signed_updates() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3])
+			.build_and_execute(|| {
+				System::set_block_number(5);
+				let oracle = RuntimeOrigin::signed(account(1));
+
+				assert_noop!(
+					update(RuntimeOrigin::signed(account(3)), vec![(account(3), 42)], 5),
+					DispatchError::BadOrigin
+				);
+				assert_noop!(update(oracle.clone(), vec![], 5), Error::<Test>::NoBalances);
+				assert_noop!(
+					update(oracle.clone(), vec![(account(3), 42)], 2),
+					Error::<Test>::StaleBalances
+				);
+				assert_noop!(
+					update(oracle.clone(), vec![(account(3), 42)], 6),
+					Error::<Test>::StaleBalances
+				);
+
+				let info = update(oracle.clone(), vec![(account(3), 42)], 3).unwrap();
+				assert_eq!(info.pays_fee, Pays::Yes);
+				assert_eq!(Balances::free_balance(account(3)), 42);
+
+				BalanceUpdateMode::set(SubmissionMode::SignedFeeless);
+				let info = update(oracle, vec![(account(3), 7)], 5).unwrap();
+				assert_eq!(info.pays_fee, Pays::No);
+				assert_eq!(Balances::free_balance(account(3)), 7);
+			});
+	}
+
+	#[test]
+	fn offchain_worker_submits_signed_updates() {
+		let (mut t, pool_state) = ExtBuilder::default().with_accounts(vec![123]).build_offchain();
+
+		t.execute_with(|| {
+			System::set_block_number(2);
+			BalanceUpdateMode::set(SubmissionMode::Signed);
+			LedgerResponse::set(Some(Ok(vec![(account(123), 42)])));
+
+			ISO8583::offchain_worker(2);
+
+			let tx = pool_state.write().transactions.pop().unwrap();
+			let tx = crate::mock::Extrinsic::decode(&mut &tx[..]).unwrap();
+			assert!(tx.signature.is_some());
+
+			let expected_accounts: crate::AccountsOf<Test> =
+				vec![(account(123), 42)].try_into().unwrap();
+			match tx.call {
+				RuntimeCall::ISO8583(crate::Call::update_accounts { accounts, at, .. }) => {
+					assert_eq!(accounts, expected_accounts);
+					assert_eq!(at, 2);
+				},
+				_ => panic!("unexpected call"),
+			}
+		});
+	}
+}
+
+mod transfer_relay {
+	use super::*;
+	use crate::{
+		types::{Hash, ISO8583FailureReason, ISO8583Status, RequestKind},
+		PendingRequests, PendingReserves,
+	};
+	use frame_support::traits::{Hooks, OffchainWorker};
+
+	#[test]
+	fn requests_are_queued() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				// self-transfers are not relayed
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(3),
+					30
+				));
+				assert_eq!(PendingRequests::<Test>::iter().count(), 0);
+
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(4),
+					30
+				));
+				let index = System::event_count() - 1;
+				let hash = Hash::from(sp_io::hashing::blake2_256(
+					&(System::parent_hash(), (1_u32, index).encode()).encode(),
+				));
+				assert_eq!(PendingRequests::<Test>::iter().collect::<Vec<_>>(), vec![(hash, 1)]);
+
+				// settled transfers leave the queue, their reverts are queued
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					FinalisedTransaction {
+						hash,
+						from: account(3),
+						to: account(4),
+						amount: 30,
+						event_id: (1_u32, index).encode().try_into().unwrap(),
+						status: ISO8583Status::Approved,
+					}
+				));
+				assert_eq!(PendingRequests::<Test>::iter().count(), 0);
+
+				assert_ok!(ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), hash));
+				let (revert, _) = PendingRequests::<Test>::iter().next().unwrap();
+				assert_ne!(revert, hash);
+			});
+	}
+
+	#[test]
+	fn reverts_need_an_approved_transfer_of_the_caller() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				assert_noop!(
+					ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), H256::zero()),
+					Error::<Test>::UnknownTransfer
+				);
+
+				let settle = |hash: Hash, amount: u128, status: ISO8583Status| {
+					ISO8583::submit_finality(
+						RuntimeOrigin::signed(account(1)),
+						FinalisedTransaction {
+							hash,
+							from: account(3),
+							to: account(4),
+							amount,
+							event_id: (1_u32, 0_u32).encode().try_into().unwrap(),
+							status,
+						},
+					)
+				};
+
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(4),
+					30
+				));
+				let hash = PendingRequests::<Test>::iter_keys().next().unwrap();
+
+				// pending transfers
+				assert_noop!(
+					ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), hash),
+					Error::<Test>::TransferNotApproved
+				);
+
+				// failed transfers
+				assert_ok!(settle(
+					hash,
+					30,
+					ISO8583Status::Failed(ISO8583FailureReason::InsufficientFunds)
+				));
+				assert_noop!(
+					ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), hash),
+					Error::<Test>::TransferNotApproved
+				);
+
+				// approved transfers of other accounts
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(4),
+					20
+				));
+				let hash = PendingRequests::<Test>::iter_keys().next().unwrap();
+				assert_ok!(settle(hash, 20, ISO8583Status::Approved));
+				assert_noop!(
+					ISO8583::initiate_revert(RuntimeOrigin::signed(account(4)), hash),
+					Error::<Test>::NotTransferSender
+				);
+
+				// reverts are not transfers
+				assert_ok!(ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), hash));
+				let revert = PendingRequests::<Test>::iter_keys().next().unwrap();
+				assert_noop!(
+					ISO8583::initiate_revert(RuntimeOrigin::signed(account(4)), revert),
+					Error::<Test>::UnknownTransfer
+				);
+
+				// pruned transfers
+				ISO8583::on_initialize(21);
+				assert_noop!(
+					ISO8583::initiate_revert(RuntimeOrigin::signed(account(3)), hash),
+					Error::<Test>::UnknownTransfer
+				);
+			});
+	}
+
+	#[test]
+	fn failed_transfers_release_reserves() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(4),
+					30
+				));
+				let hash = PendingRequests::<Test>::iter_keys().next().unwrap();
+
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					FinalisedTransaction {
+						hash,
+						from: account(3),
+						to: account(4),
+						amount: 30,
+						event_id: (1_u32, System::event_count() - 1).encode().try_into().unwrap(),
+						status: ISO8583Status::Failed(ISO8583FailureReason::InsufficientFunds),
+					}
+				));
+
+				assert_eq!(PendingReserves::<Test>::get(account(3)), 0);
+				assert_eq!(Balances::reserved_balance(account(3)), 0);
+				assert_eq!(Balances::free_balance(account(3)), INITIAL_BALANCE);
+				assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE);
+			});
+	}
+
+	#[test]
+	fn requests_are_indexed_until_pruned() {
+		let mut t = ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_offchain()
+			.0;
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			assert_ok!(ISO8583::initiate_transfer(
+				RuntimeOrigin::signed(account(3)),
+				account(3),
+				account(4),
+				30
+			));
+		});
+		t.persist_offchain_overlay();
+
+		let hash = t.execute_with(|| {
+			let hash = PendingRequests::<Test>::iter_keys().next().unwrap();
+			let request = ISO8583::transfer_request(&hash).unwrap();
+			assert_eq!(
+				request.kind,
+				RequestKind::Transfer { from: account(3), to: account(4), amount: 30 }
+			);
+
+			// failed transfers are settled as well
+			assert_ok!(ISO8583::submit_finality(
+				RuntimeOrigin::signed(account(1)),
+				FinalisedTransaction {
+					hash,
+					from: account(3),
+					to: account(4),
+					amount: 30,
+					event_id: request.event_id,
+					status: ISO8583Status::Failed(ISO8583FailureReason::InsufficientFunds),
+				}
+			));
+			assert_eq!(Balances::reserved_balance(account(3)), 0);
+			ISO8583::do_try_state().unwrap();
+
+			hash
+		});
+		t.persist_offchain_overlay();
+
+		// settled transfers stay indexed for reverts
+		t.execute_with(|| {
+			assert!(ISO8583::transfer_request(&hash).is_some());
+			ISO8583::on_initialize(21);
+		});
+		t.persist_offchain_overlay();
+
+		t.execute_with(|| {
+			assert_eq!(ISO8583::transfer_request(&hash), None);
+		});
+	}
+
+	#[test]
+	fn requests_are_relayed_and_attested() {
+		let (mut t, pool_state) = ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_offchain();
+
+		t.execute_with(|| {
+			System::set_block_number(1);
+			assert_ok!(ISO8583::initiate_transfer(
+				RuntimeOrigin::signed(account(3)),
+				account(3),
+				account(4),
+				30
+			));
+		});
+		t.persist_offchain_overlay();
+
+		t.execute_with(|| {
+			System::set_block_number(2);
+			let hash = PendingRequests::<Test>::iter_keys().next().unwrap();
+
+			// balances are in sync
+			LedgerResponse::set(Some(Ok(vec![])));
+			RelayStatus::set(Some(ISO8583Status::Approved));
+
+			ISO8583::offchain_worker(2);
+			assert_eq!(RelayedRequests::get(), vec![hash]);
+
+			let tx = pool_state.write().transactions.pop().unwrap();
+			let tx = crate::mock::Extrinsic::decode(&mut &tx[..]).unwrap();
+			assert!(tx.signature.is_some());
+			let transaction = match tx.call {
+				RuntimeCall::ISO8583(crate::Call::submit_finality { transaction }) => transaction,
+				_ => panic!("unexpected call"),
+			};
+			assert_eq!(transaction.hash, hash);
+			assert_eq!(
+				(transaction.from, transaction.to, transaction.amount),
+				(account(3), account(4), 30)
+			);
+			assert_eq!(ISO8583::initiated_at(&transaction.event_id), Some(1));
+
+			// attested requests are not relayed again
+			ISO8583::offchain_worker(4);
+			assert_eq!(RelayedRequests::get(), vec![hash]);
+
+			// settled requests leave the queue
+			assert_ok!(ISO8583::submit_finality(RuntimeOrigin::signed(account(1)), transaction));
+			assert_eq!(PendingRequests::<Test>::get(hash), None);
+			assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE + 30);
+
+			ISO8583::do_try_state().unwrap();
+		});
+	}
+}
+
+mod request_signer {
+	use super::*;
+	use crate::ledger::{KeystoreSigner, LedgerError, RequestSigner};
+	use sp_core::sr25519;
+	use sp_keystore::{testing::MemoryKeystore, Keystore, KeystoreExt};
+	use sp_runtime::{traits::Verify, RuntimeAppPublic};
+
+	#[test]
+	fn requests_are_signed_with_the_node_key() {
+		let keystore = MemoryKeystore::new();
+		let mut t = ExtBuilder::default().build();
+		t.register_extension(KeystoreExt::new(keystore.clone()));
+
+		t.execute_with(|| {
+			// nodes without a key can not sign
+			assert_eq!(KeystoreSigner::<Test>::sign(b"message"), Err(LedgerError::NoSigner));
+
+			let public = keystore
+				.sr25519_generate_new(
+					crate::crypto::Public::ID,
+					Some(&format!("{}/iso8583", PHRASE)),
+				)
+				.unwrap();
+
+			let signature = KeystoreSigner::<Test>::sign(b"message").unwrap();
+			let signature = sr25519::Signature::decode(&mut &signature[..]).unwrap();
+			assert!(signature.verify(&b"message"[..], &public));
+			assert!(!signature.verify(&b"other message"[..], &public));
+		});
+	}
+}
+
+mod iso8583_ledger {
+	use super::*;
+	use crate::{
+		ledger::{
+			iso8583::{
+				balance, balance_inquiry, frame, settlement, transaction, transfer_request,
+				unframe, Message, RESPONSE_MTI, REVERSAL_MTI, REVERSAL_RESPONSE_MTI,
+				TRANSFER_RESPONSE_MTI,
+			},
+			Iso8583Ledger, LedgerError, TransferRelay,
+		},
+		types::{
+			Hash, ISO8583FailureReason, ISO8583Status, LedgerView, RequestKind, TransferRequest,
+			TransferRequestOf,
+		},
+	};
+	use sp_core::offchain::{testing, OffchainDbExt, OffchainWorkerExt, StorageKind};
+
+	fn transfer(hash: Hash) -> (Hash, TransferRequestOf<Test>) {
+		let kind = RequestKind::Transfer { from: account(1), to: account(2), amount: 12_340_000 };
+		(hash, TransferRequest { event_id: (1_u32, 2_u32).encode().try_into().unwrap(), kind })
+	}
+
+	#[test]
+	fn balance_inquiry_is_encoded() {
+		let message = balance_inquiry(42, &account(1)).encode();
+
+		let mut expected = b"0100".to_vec();
+		// fields 3, 11 and 48
+		expected.extend_from_slice(b"2020000000010000");
+		expected.extend_from_slice(b"310000");
+		expected.extend_from_slice(b"000042");
+		expected.extend_from_slice(b"064");
+		expected.extend_from_slice("01".repeat(32).as_bytes());
+
+		assert_eq!(message, expected);
+		assert_eq!(Message::decode(&message).unwrap(), balance_inquiry(42, &account(1)));
+	}
+
+	#[test]
+	fn transfer_requests_are_encoded() {
+		let hash = Hash::repeat_byte(7);
+		let (_, request) = transfer(hash);
+		let message = transfer_request::<Test>(&hash, &request.kind);
+
+		let mut expected = b"0200".to_vec();
+		// fields 3, 4, 11, 37 and 48
+		expected.extend_from_slice(b"3020000008010000");
+		expected.extend_from_slice(b"400000");
+		expected.extend_from_slice(b"000000001234");
+		// trace numbers of the transaction ID, 0x07070707 modulo 1000000
+		expected.extend_from_slice(b"901063");
+		expected.extend_from_slice(b"070707070707");
+		expected.extend_from_slice(b"192");
+		expected.extend_from_slice(
+			format!("{}{}{}", "07".repeat(32), "01".repeat(32), "02".repeat(32)).as_bytes(),
+		);
+
+		assert_eq!(message.encode(), expected);
+
+		// reverts refer to the reverted transaction
+		let revert = RequestKind::Revert { hash: Hash::repeat_byte(9), who: account(1) };
+		let message = transfer_request::<Test>(&hash, &revert);
+
+		assert_eq!(message.mti, *REVERSAL_MTI);
+		assert_eq!(message.field(4), None);
+		assert_eq!(message.field(37), Some(&b"070707070707"[..]));
+		assert_eq!(
+			message.field(48).unwrap(),
+			format!("{}{}", "07".repeat(32), "09".repeat(32)).as_bytes()
+		);
+	}
+
+	#[test]
+	fn transfer_responses_are_decoded() {
+		let (hash, request) = transfer(Hash::repeat_byte(7));
+		let settlements = vec![settlement::<Test>(hash, &request).unwrap()];
+
+		let mut message = Message::new(*TRANSFER_RESPONSE_MTI);
+		message.set(4, b"000000001234".to_vec());
+		message.set(39, b"51".to_vec());
+		message.set(
+			48,
+			hex::encode((Hash::repeat_byte(7), account(1), account(2)).encode()).into_bytes(),
+		);
+
+		let transaction = transaction::<Test>(&message, &settlements).unwrap();
+		assert_eq!(transaction.hash, Hash::repeat_byte(7));
+		assert_eq!((transaction.from, transaction.to), (account(1), account(2)));
+		assert_eq!(transaction.amount, 12_340_000);
+		assert_eq!(transaction.event_id, request.event_id);
+		assert_eq!(
+			transaction.status,
+			ISO8583Status::Failed(ISO8583FailureReason::InsufficientFunds)
+		);
+
+		message.set(39, b"00".to_vec());
+		assert_eq!(
+			transaction::<Test>(&message, &settlements).unwrap().status,
+			ISO8583Status::Approved
+		);
+
+		// answers to requests that were not sent
+		let (other, request) = transfer(Hash::repeat_byte(8));
+		assert_eq!(
+			transaction::<Test>(&message, &[settlement::<Test>(other, &request).unwrap()]),
+			Err(LedgerError::InvalidResponse)
+		);
+
+		// balance inquiry response
+		message.mti = *RESPONSE_MTI;
+		assert_eq!(transaction::<Test>(&message, &settlements), Err(LedgerError::InvalidResponse));
+	}
+
+	#[test]
+	fn responses_must_match_the_request() {
+		let (hash, request) = transfer(Hash::repeat_byte(7));
+		let settlements = vec![settlement::<Test>(hash, &request).unwrap()];
+
+		let mut message = Message::new(*TRANSFER_RESPONSE_MTI);
+		message.set(4, b"000000001234".to_vec());
+		message.set(39, b"00".to_vec());
+		message.set(
+			48,
+			hex::encode((Hash::repeat_byte(7), account(1), account(2)).encode()).into_bytes(),
+		);
+		assert!(transaction::<Test>(&message, &settlements).is_ok());
+
+		// other amount
+		message.set(4, b"000000001235".to_vec());
+		assert_eq!(transaction::<Test>(&message, &settlements), Err(LedgerError::InvalidResponse));
+
+		// other recipient
+		message.set(4, b"000000001234".to_vec());
+		message.set(
+			48,
+			hex::encode((Hash::repeat_byte(7), account(1), account(3)).encode()).into_bytes(),
+		);
+		assert_eq!(transaction::<Test>(&message, &settlements), Err(LedgerError::InvalidResponse));
+
+		// reverts move the funds of the reverted transfer back
+		let revert = TransferRequest {
+			event_id: (1_u32, 3_u32).encode().try_into().unwrap(),
+			kind: RequestKind::Revert { hash, who: account(1) },
+		};
+		let mut ext = sp_io::TestExternalities::default();
+		ext.register_extension(OffchainDbExt::new(testing::TestOffchainExt::new().0));
+		ext.execute_with(|| {
+			let revert_hash = Hash::repeat_byte(9);
+
+			// not indexed
+			assert!(settlement::<Test>(revert_hash, &revert).is_none());
+
+			sp_io::offchain::local_storage_set(
+				StorageKind::PERSISTENT,
+				&[crate::TRANSFER_REQUEST_PREFIX, hash.as_bytes()].concat(),
+				&request.encode(),
+			);
+			let settlements = vec![settlement::<Test>(revert_hash, &revert).unwrap()];
+
+			let mut message = Message::new(*REVERSAL_RESPONSE_MTI);
+			message.set(4, b"000000001234".to_vec());
+			message.set(39, b"00".to_vec());
+			message
+				.set(48, hex::encode((revert_hash, account(2), account(1)).encode()).into_bytes());
+
+			let transaction = transaction::<Test>(&message, &settlements).unwrap();
+			assert_eq!((transaction.from, transaction.to), (account(2), account(1)));
+			assert_eq!(transaction.event_id, revert.event_id);
+
+			// funds of the transfer itself
+			message
+				.set(48, hex::encode((revert_hash, account(1), account(2)).encode()).into_bytes());
+			assert_eq!(
+				transaction::<Test>(&message, &settlements),
+				Err(LedgerError::InvalidResponse)
+			);
+		});
+	}
+
+	#[test]
+	fn invalid_answers_are_skipped() {
+		let (offchain, state) = testing::TestOffchainExt::new();
+		let mut t = ExtBuilder::default().build();
+		t.register_extension(OffchainDbExt::new(offchain.clone()));
+		t.register_extension(OffchainWorkerExt::new(offchain));
+
+		let requests = vec![transfer(Hash::repeat_byte(7)), transfer(Hash::repeat_byte(8))];
+		let answer = |hash: Hash, amount: &[u8]| {
+			let mut message = Message::new(*TRANSFER_RESPONSE_MTI);
+			message.set(4, amount.to_vec());
+			message.set(39, b"00".to_vec());
+			message.set(48, hex::encode((hash, account(1), account(2)).encode()).into_bytes());
+			message.encode()
+		};
+
+		state.write().expect_request(testing::PendingRequest {
+			method: "POST".into(),
+			uri: "http://localhost:3001/iso8583".into(),
+			body: frame(
+				&requests
+					.iter()
+					.map(|(hash, request)| transfer_request::<Test>(hash, &request.kind).encode())
+					.collect::<Vec<_>>(),
+			),
+			// the first answer does not match its request
+			response: Some(frame(&[
+				answer(Hash::repeat_byte(7), b"000000001235"),
+				answer(Hash::repeat_byte(8), b"000000001234"),
+			])),
+			sent: true,
+			headers: vec![("Content-Type".to_string(), "application/octet-stream".to_string())],
+			..Default::default()
+		});
+
+		t.execute_with(|| {
+			let transactions =
+				Iso8583Ledger::<Test>::relay(b"http://localhost:3001", &requests, 1).unwrap();
+
+			assert_eq!(transactions.len(), 1);
+			assert_eq!(transactions[0].hash, Hash::repeat_byte(8));
+			assert_eq!(transactions[0].status, ISO8583Status::Approved);
+		});
+	}
+
+	#[test]
+	fn additional_amounts_are_decoded() {
+		// ledger balance only, 12.34 in minor units
+		assert_eq!(balance::<Test>(b"0001840C000000001234"), Err(LedgerError::InvalidResponse));
+		BalanceView::set(LedgerView::Ledger);
+		assert_eq!(balance::<Test>(b"0001840C000000001234"), Ok(12_340_000));
+
+		// amount of the balance view is used
+		assert_eq!(balance::<Test>(b"0001840C0000000012340002840C000000000100"), Ok(12_340_000));
+		BalanceView::set(LedgerView::Available);
+		assert_eq!(balance::<Test>(b"0001840C0000000012340002840C000000000100"), Ok(1_000_000));
+
+		// debit balance
+		assert_eq!(balance::<Test>(b"0002840D000000000100"), Ok(0));
+
+		assert_eq!(balance::<Test>(b""), Err(LedgerError::InvalidResponse));
+		assert_eq!(balance::<Test>(b"0002840X000000000100"), Err(LedgerError::InvalidResponse));
+		assert_eq!(balance::<Test>(b"0003840C000000000100"), Err(LedgerError::InvalidResponse));
+	}
+
+	#[test]
+	fn messages_are_framed() {
+		let messages = vec![b"0100".to_vec(), b"01100000".to_vec()];
+		let framed = frame(&messages);
+
+		assert_eq!(&framed[..6], &[0, 4, b'0', b'1', b'0', b'0']);
+		assert_eq!(unframe(&framed).unwrap(), vec![&b"0100"[..], &b"01100000"[..]]);
+
+		// truncated message
+		assert_eq!(unframe(&framed[..framed.len() - 1]), Err(LedgerError::InvalidResponse));
+	}
+
+	#[test]
+	fn malformed_messages_are_rejected() {
+		// too short
+		assert_eq!(Message::decode(b"0110"), Err(LedgerError::InvalidResponse));
+		// secondary bitmap
+		assert_eq!(Message::decode(b"0110C000000000000000"), Err(LedgerError::InvalidResponse));
+		// unsupported field 5
+		assert_eq!(
+			Message::decode(b"01100800000000000000000000000000"),
+			Err(LedgerError::InvalidResponse)
+		);
+		// missing data of field 3
+		assert_eq!(Message::decode(b"0110200000000000000031"), Err(LedgerError::InvalidResponse));
+	}
+}
+
+mod json_ledger {
+	use super::*;
+	use crate::{
+		ledger::{
+			json::{parse_page, utf8, Page},
+			LedgerError,
+		},
+		types::ProcessorSignature,
+	};
+
+	fn parse(body: &str) -> Result<Page<Test>, LedgerError> {
+		parse_page::<Test>(body.as_bytes())
+	}
+
+	fn hex_account(id: u8) -> String {
+		hex::encode(account(id).encode())
+	}
+
+	#[test]
+	fn fields_are_read_by_name() {
+		ExtBuilder::default().build().execute_with(|| {
+			let body = format!(
+				r#"[{{"currency":"USD","balance":"100.11","accountId":"{}"}},{{"account_id":"{}","balance":2.5}}]"#,
+				hex_account(1),
+				hex_account(2)
+			);
+
+			let page = parse(&body).unwrap();
+			assert_eq!(page.accounts, vec![(account(1), 100_110_000), (account(2), 2_500_000)]);
+			assert_eq!(page.signature, None);
+			assert_eq!(page.next, None);
+		});
+	}
+
+	#[test]
+	fn pages_carry_cursor_and_signature() {
+		ExtBuilder::default().build().execute_with(|| {
+			let body = format!(
+				r#"{{"next":"c2","signature":"{}","accounts":[{{"accountId":"{}","balance":"1"}}]}}"#,
+				"00".repeat(64),
+				hex_account(1),
+			);
+
+			let page = parse(&body).unwrap();
+			assert_eq!(page.accounts, vec![(account(1), 1_000_000)]);
+			assert_eq!(page.signature, Some(ProcessorSignature::from_raw([0; 64])));
+			assert_eq!(page.next, Some("c2".chars().collect()));
+
+			let page = parse(r#"{"accounts":[],"next":null}"#).unwrap();
+			assert_eq!(page.next, None);
+		});
+	}
+
+	#[test]
+	fn changes_carry_change_cursor() {
+		ExtBuilder::default().build().execute_with(|| {
+			let body = format!(
+				r#"{{"cursor":"1042","accounts":[{{"accountId":"{}","balance":"1"}}]}}"#,
+				hex_account(1)
+			);
+
+			let page = parse(&body).unwrap();
+			assert_eq!(page.accounts, vec![(account(1), 1_000_000)]);
+			assert_eq!(page.cursor, Some("1042".chars().collect()));
+			assert_eq!(utf8(&page.cursor.unwrap()), b"1042".to_vec());
+
+			assert_eq!(parse(r#"{"accounts":[],"cursor":null}"#).unwrap().cursor, None);
+			assert_eq!(
+				parse(r#"{"accounts":[],"cursor":1042}"#).err(),
+				Some(LedgerError::InvalidCursor)
+			);
+		});
+	}
+
+	#[test]
+	fn malformed_responses_are_distinguished() {
+		ExtBuilder::default().build().execute_with(|| {
+			assert_eq!(parse_page::<Test>(&[0xff]).err(), Some(LedgerError::InvalidEncoding));
+			assert_eq!(parse("[{").err(), Some(LedgerError::InvalidJson));
+			assert_eq!(parse(r#""accounts""#).err(), Some(LedgerError::UnexpectedShape));
+			assert_eq!(parse("[1]").err(), Some(LedgerError::UnexpectedShape));
+			assert_eq!(
+				parse(r#"{"next":"c2"}"#).err(),
+				Some(LedgerError::MissingField("accounts"))
+			);
+			assert_eq!(
+				parse(r#"[{"balance":"1"}]"#).err(),
+				Some(LedgerError::MissingField("accountId"))
+			);
+			assert_eq!(
+				parse(&format!(r#"[{{"accountId":"{}"}}]"#, hex_account(1))).err(),
+				Some(LedgerError::MissingField("balance"))
+			);
+			assert_eq!(
+				parse(r#"[{"accountId":"7b","balance":"1"}]"#).err(),
+				Some(LedgerError::InvalidAccountId)
+			);
+			assert_eq!(
+				parse(&format!(r#"[{{"accountId":"{}","balance":"1.2.3"}}]"#, hex_account(1)))
+					.err(),
+				Some(LedgerError::InvalidBalance)
+			);
+			assert_eq!(
+				parse(&format!(r#"[{{"accountId":"{}","balance":-1}}]"#, hex_account(1))).err(),
+				Some(LedgerError::InvalidBalance)
+			);
+			assert_eq!(
+				parse(r#"{"accounts":[],"next":2}"#).err(),
+				Some(LedgerError::InvalidCursor)
+			);
+			assert_eq!(
+				parse(r#"{"accounts":[],"signature":"00"}"#).err(),
+				Some(LedgerError::InvalidSignature)
+			);
+		});
+	}
+}
+
+mod processor_signatures {
+	use super::*;
+	use crate::{types::UpdateAccountsPayload, AccountsOf, PaymentProcessorKey};
+	use frame_support::unsigned::ValidateUnsigned;
+	use frame_system::offchain::SignedPayload;
+	use sp_core::{sr25519, Pair};
+	use sp_runtime::{
+		transaction_validity::{InvalidTransaction, TransactionSource},
+		RuntimeAppPublic,
+	};
+
+	fn processor() -> sr25519::Pair {
+		sr25519::Pair::from_seed(&[7; 32])
+	}
+
+	fn sign(accounts: &AccountsOf<Test>, at: u64) -> sr25519::Signature {
+		processor().sign(&(accounts, at).encode())
+	}
+
+	fn build() -> sp_io::TestExternalities {
+		ExtBuilder::default().with_accounts(vec![123, 125]).build_offchain().0
+	}
+
+	#[test]
+	fn admin_rotates_processor_key() {
+		build().execute_with(|| {
+			System::set_block_number(1);
+
+			assert_noop!(
+				ISO8583::set_payment_processor_key(
+					RuntimeOrigin::signed(account(123)),
+					Some(processor().public())
+				),
+				DispatchError::BadOrigin
+			);
+
+			assert_ok!(ISO8583::set_payment_processor_key(
+				RuntimeOrigin::root(),
+				Some(processor().public())
+			));
+			assert_eq!(PaymentProcessorKey::<Test>::get(), Some(processor().public()));
+			System::assert_last_event(RuntimeEvent::ISO8583(
+				crate::Event::<Test>::PaymentProcessorKeyChanged {
+					key: Some(processor().public()),
+				},
+			));
+
+			assert_ok!(ISO8583::set_payment_processor_key(RuntimeOrigin::root(), None));
+			assert_eq!(PaymentProcessorKey::<Test>::get(), None);
+		});
+	}
+
+	#[test]
+	fn balances_must_be_signed_by_processor() {
+		let (mut t, pool_state) =
+			ExtBuilder::default().with_accounts(vec![123, 125]).build_offchain();
+
+		t.execute_with(|| {
+			PaymentProcessorKey::<Test>::put(processor().public());
+			LedgerResponse::set(Some(Ok(vec![
+				(account(123), 42),
+				(account(125), INITIAL_BALANCE),
+			])));
+
+			// unsigned balances are rejected
+			assert_eq!(
+				ISO8583::fetch_and_submit_updated_balances(
+					vec![account(123), account(125)],
+					vec![]
+				),
+				Err("Invalid payment processor signature")
+			);
+
+			// signature over other balances is rejected
+			let forged: AccountsOf<Test> = vec![(account(123), 1)].try_into().unwrap();
+			LedgerSignature::set(Some(sign(&forged, 0)));
+			assert!(ISO8583::fetch_and_submit_updated_balances(
+				vec![account(123), account(125)],
+				vec![]
+			)
+			.is_err());
+			assert!(pool_state.read().transactions.is_empty());
+
+			// signed balances are submitted as a whole
+			let expected: AccountsOf<Test> =
+				vec![(account(123), 42), (account(125), INITIAL_BALANCE)].try_into().unwrap();
+			LedgerSignature::set(Some(sign(&expected, 0)));
+			assert_ok!(ISO8583::fetch_and_submit_updated_balances(
+				vec![account(123), account(125)],
+				vec![]
+			));
+
+			let tx = pool_state.write().transactions.pop().unwrap();
+			let tx = crate::mock::Extrinsic::decode(&mut &tx[..]).unwrap();
+
+			match tx.call {
+				RuntimeCall::ISO8583(crate::Call::update_accounts_unsigned { payload, .. }) => {
+					assert_eq!(payload.accounts, expected);
+					assert_eq!(payload.at, 0);
+					assert_eq!(payload.processor_signature, Some(sign(&expected, 0)));
+				},
+				_ => panic!("unexpected call"),
+			}
+		});
+	}
+
+	#[test]
+	fn unsigned_updates_are_validated() {
+		build().execute_with(|| {
+			System::set_block_number(5);
+			PaymentProcessorKey::<Test>::put(processor().public());
+
+			let accounts: AccountsOf<Test> = vec![(account(123), 42)].try_into().unwrap();
+			let validate = |at: u64, processor_signature: Option<sr25519::Signature>| {
+				let payload = UpdateAccountsPayload {
+					public: crate::crypto::Public::all()[0].clone().into(),
+					accounts: accounts.clone(),
+					last_key: Some(vec![].try_into().unwrap()),
+					at,
+					processor_signature,
+					endpoint: Some(0),
+				};
+				let signature = payload.sign::<crate::crypto::Iso8583AuthId>().unwrap();
+
+				ISO8583::validate_unsigned(
+					TransactionSource::Local,
+					&crate::Call::update_accounts_unsigned { payload, signature },
+				)
+			};
+
+			assert!(validate(5, Some(sign(&accounts, 5))).is_ok());
+			// queried within `OffchainWorkerInterval`
+			assert!(validate(3, Some(sign(&accounts, 3))).is_ok());
+
+			assert_eq!(validate(5, None), InvalidTransaction::BadProof.into());
+			assert_eq!(validate(5, Some(sign(&accounts, 4))), InvalidTransaction::BadProof.into());
+			assert_eq!(validate(6, Some(sign(&accounts, 6))), InvalidTransaction::Future.into());
+			assert_eq!(validate(2, Some(sign(&accounts, 2))), InvalidTransaction::Stale.into());
+
+			// any balances are accepted without a processor key
+			PaymentProcessorKey::<Test>::kill();
+			assert!(validate(5, None).is_ok());
+		});
+	}
+}
+
+mod migrations {
+	use super::{failover::endpoints, *};
+	use crate::{
+		migrations::{
+			v1::MigrateToV1,
+			v2::{MigrateToV2, PaymentProcessorUrl},
+			v3::MigrateToV3,
+		},
+		types::{AccountInfo, OracleInfo},
+		Accounts, DirtyAccounts, OracleAccounts, Pallet, ProcessorEndpoints,
+	};
+	use frame_support::{
+		storage::unhashed,
+		traits::{GetStorageVersion, OnRuntimeUpgrade, StorageVersion},
+	};
+
+	#[test]
+	fn migrate_to_v1_works() {
+		ExtBuilder::default().build().execute_with(|| {
+			System::set_block_number(10);
+
+			// v0 storage: unit values
+			StorageVersion::new(0).put::<Pallet<Test>>();
+			for id in [3, 4] {
+				unhashed::put_raw(&Accounts::<Test>::hashed_key_for(account(id)), &[]);
+			}
+			unhashed::put_raw(&OracleAccounts::<Test>::hashed_key_for(account(1)), &[]);
+
+			MigrateToV1::<Test>::on_runtime_upgrade();
+
+			assert_eq!(Pallet::<Test>::on_chain_storage_version(), 1);
+			assert_eq!(
+				Accounts::<Test>::get(account(3)),
+				Some(AccountInfo { registered_at: 10, last_synced_at: None })
+			);
+			assert_eq!(
+				Accounts::<Test>::get(account(4)),
+				Some(AccountInfo { registered_at: 10, last_synced_at: None })
+			);
+			assert_eq!(
+				OracleAccounts::<Test>::get(account(1)),
+				Some(OracleInfo { registered_at: 10 })
+			);
+
+			// running the migration again is a no-op
+			System::set_block_number(11);
+			MigrateToV1::<Test>::on_runtime_upgrade();
+
+			assert_eq!(
+				OracleAccounts::<Test>::get(account(1)),
+				Some(OracleInfo { registered_at: 10 })
+			);
+		});
+	}
+
+	#[test]
+	fn migrate_to_v2_works() {
+		ExtBuilder::default().build().execute_with(|| {
+			StorageVersion::new(1).put::<Pallet<Test>>();
+			ProcessorEndpoints::<Test>::kill();
+			PaymentProcessorUrl::<Test>::put(
+				crate::StorageKey::try_from(b"http://localhost:3001".to_vec()).unwrap(),
+			);
+
+			MigrateToV2::<Test>::on_runtime_upgrade();
+
+			assert_eq!(Pallet::<Test>::on_chain_storage_version(), 2);
+			assert!(!PaymentProcessorUrl::<Test>::exists());
+			assert_eq!(ProcessorEndpoints::<Test>::get(), endpoints(&["http://localhost:3001"]));
+
+			// running the migration again is a no-op
+			ProcessorEndpoints::<Test>::kill();
+			MigrateToV2::<Test>::on_runtime_upgrade();
+			assert!(ProcessorEndpoints::<Test>::get().is_empty());
+		});
+	}
+
+	#[test]
+	fn migrate_to_v3_works() {
+		ExtBuilder::default().build().execute_with(|| {
+			System::set_block_number(10);
+
+			// v2 storage: queued accounts only
+			StorageVersion::new(2).put::<Pallet<Test>>();
+			unhashed::put(&DirtyAccounts::<Test>::hashed_key(), &vec![account(3), account(4)]);
+
+			MigrateToV3::<Test>::on_runtime_upgrade();
+
+			assert_eq!(Pallet::<Test>::on_chain_storage_version(), 3);
+			assert_eq!(
+				DirtyAccounts::<Test>::get().into_inner(),
+				vec![(account(3), 10), (account(4), 10)]
+			);
+
+			// running the migration again is a no-op
+			System::set_block_number(11);
+			MigrateToV3::<Test>::on_runtime_upgrade();
+
+			assert_eq!(
+				DirtyAccounts::<Test>::get().into_inner(),
+				vec![(account(3), 10), (account(4), 10)]
+			);
+		});
+	}
+}
+
+mod try_state {
+	use super::*;
+	use crate::{Allowances, LastIteratedStorageKey, PendingReserves};
+	use frame_support::traits::ReservableCurrency;
+
+	#[test]
+	fn settlement_releases_pending_reserves() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(3)),
+					account(3),
+					account(4),
+					30
+				));
+				assert_eq!(PendingReserves::<Test>::get(account(3)), 30);
+
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					FinalisedTransaction {
+						from: account(3),
+						to: account(4),
+						amount: 30,
+						hash: H256::from([1; 32]),
+						event_id: (1_u32, 0_u32).encode().try_into().unwrap(),
+						status: crate::types::ISO8583Status::Approved,
+					}
+				));
+
+				assert!(!PendingReserves::<Test>::contains_key(account(3)));
+				assert_eq!(Balances::reserved_balance(account(3)), 0);
+				assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE + 30);
+			});
+	}
+
+	#[test]
+	fn unexplained_reserve_is_detected() {
+		ExtBuilder::default().with_accounts(vec![3]).build().execute_with(|| {
+			assert_ok!(ISO8583::do_try_state());
+
+			assert_ok!(Balances::reserve(&account(3), 10));
+
+			assert!(ISO8583::do_try_state().is_err());
+		});
+	}
+
+	#[test]
+	fn allowances_are_given_by_registered_accounts() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3])
+			.build_and_execute(|| {
+				// unregistered accounts can not give allowances
+				assert_noop!(
+					ISO8583::approve(RuntimeOrigin::signed(account(1)), account(3), 10),
+					Error::<Test>::SourceNotRegistered,
+				);
+
+				assert_ok!(ISO8583::approve(RuntimeOrigin::signed(account(3)), account(4), 10));
+
+				// allowances are removed with the account
+				assert_ok!(ISO8583::remove(RuntimeOrigin::signed(account(1)), account(3)));
+				assert_eq!(Allowances::<Test>::iter_prefix(account(3)).count(), 0);
+			});
+	}
+
+	#[test]
+	fn pallet_account_can_not_be_registered() {
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
+			assert_noop!(
+				ISO8583::register(
+					RuntimeOrigin::signed(account(1)),
+					<Test as crate::Config>::PalletAccount::get(),
+					100
+				),
+				Error::<Test>::PalletAccountNotAllowed,
+			);
+		});
+	}
+
+	#[test]
+	fn last_key_outside_of_accounts_is_detected() {
+		ExtBuilder::default().build().execute_with(|| {
+			LastIteratedStorageKey::<Test>::put(crate::StorageKey::truncate_from(vec![1, 2, 3]));
+
+			assert!(ISO8583::do_try_state().is_err());
+		});
+	}
+}
+
+mod quorum {
+	use super::*;
+	use crate::{
+		types::{ISO8583FailureReason, ISO8583Status},
+		Attestations, Expirations, SettledTransactions,
+	};
+	use frame_support::traits::Hooks;
+
+	fn transfer(hash: u8, status: ISO8583Status) -> crate::types::FinalisedTransactionOf<Test> {
+		FinalisedTransaction {
+			from: account(3),
+			to: account(4),
+			amount: 20,
+			hash: H256::from([hash; 32]),
+			event_id: (1_u32, 0_u32).encode().try_into().unwrap(),
+			status,
+		}
+	}
+
+	#[test]
+	fn settles_once_threshold_is_reached() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2])
+			.with_accounts(vec![3, 4, 5])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+				FinalityThreshold::set(2);
+
+				let transaction = transfer(1, ISO8583Status::Approved);
+				let hash = transaction.hash;
+
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transaction.clone()
+				));
+
+				System::assert_has_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::FinalityAttested { oracle: account(1), hash },
+				));
+
+				// not settled yet
+				assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE);
+				assert!(!SettledTransactions::<Test>::contains_key(hash));
+
+				// oracle can attest only once
+				assert_noop!(
+					ISO8583::submit_finality(
+						RuntimeOrigin::signed(account(1)),
+						transaction.clone()
+					),
+					Error::<Test>::AlreadyAttested,
+				);
+
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(2)),
+					transaction.clone()
+				));
+
+				System::assert_has_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::QuorumReached {
+						hash,
+						oracles: vec![account(1), account(2)],
+					},
+				));
+				System::assert_has_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::ProcessedTransaction { transaction: transaction.clone() },
+				));
+
+				// settled
+				assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE + 20);
+				assert_eq!(SettledTransactions::<Test>::get(hash), Some(1));
+				assert_eq!(Attestations::<Test>::iter_prefix(hash).count(), 0);
+
+				// late attestations are rejected
+				assert_ok!(ISO8583::register_oracle(RuntimeOrigin::root(), account(5)));
+				assert_noop!(
+					ISO8583::submit_finality(RuntimeOrigin::signed(account(5)), transaction),
+					Error::<Test>::AlreadySettled,
+				);
+			});
+	}
+
+	#[test]
+	fn conflicting_attestations_are_flagged() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2, 5])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+				FinalityThreshold::set(2);
+
+				let approved = transfer(1, ISO8583Status::Approved);
+				let failed =
+					transfer(1, ISO8583Status::Failed(ISO8583FailureReason::InsufficientFunds));
+				let hash = approved.hash;
+
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					approved.clone()
+				));
+				assert_ok!(ISO8583::submit_finality(RuntimeOrigin::signed(account(2)), failed));
+
+				System::assert_has_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::ConflictingAttestation { oracle: account(2), hash },
+				));
+
+				// no quorum for either version
+				assert_eq!(Attestations::<Test>::iter_prefix(hash).count(), 2);
+				assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE);
+
+				assert_ok!(ISO8583::submit_finality(RuntimeOrigin::signed(account(5)), approved));
+
+				System::assert_has_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::QuorumReached {
+						hash,
+						oracles: vec![account(1), account(5)],
+					},
+				));
+				assert_eq!(Balances::free_balance(account(4)), INITIAL_BALANCE + 20);
+				assert_eq!(Attestations::<Test>::iter_prefix(hash).count(), 0);
+
+				// slashing the dissenting oracle is left to the admin
+				assert!(!System::events().iter().any(|record| matches!(
+					record.event,
+					RuntimeEvent::ISO8583(crate::Event::<Test>::DeductFunds { .. })
+				)));
+				assert_eq!(Balances::reserved_balance(account(2)), OracleBond::get());
+			});
+	}
+
+	#[test]
+	fn settled_transactions_expire() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				let transaction = transfer(1, ISO8583Status::Approved);
+				let hash = transaction.hash;
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transaction.clone()
+				));
+
+				// kept for `RetentionPeriod` blocks
+				System::set_block_number(20);
+				ISO8583::on_initialize(20);
+				assert_eq!(SettledTransactions::<Test>::get(hash), Some(1));
+
+				System::set_block_number(21);
+				ISO8583::on_initialize(21);
+				assert_eq!(SettledTransactions::<Test>::get(hash), None);
+				assert_eq!(Expirations::<Test>::iter().count(), 0);
+
+				// the transfer can not be settled again
+				assert_noop!(
+					ISO8583::submit_finality(RuntimeOrigin::signed(account(2)), transaction),
+					Error::<Test>::Expired,
+				);
+			});
+	}
+
+	#[test]
+	fn unsettled_attestations_expire() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+				FinalityThreshold::set(2);
+
+				let transaction = transfer(1, ISO8583Status::Approved);
+				let hash = transaction.hash;
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transaction
+				));
+
+				System::set_block_number(21);
+				ISO8583::on_initialize(21);
+
+				assert_eq!(Attestations::<Test>::iter_prefix(hash).count(), 0);
+				assert_eq!(SettledTransactions::<Test>::get(hash), None);
+				System::assert_has_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::TransactionExpired { hash },
+				));
+			});
+	}
+
+	#[test]
+	fn pruning_is_bounded() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				for hash in 0..5 {
+					assert_ok!(ISO8583::submit_finality(
+						RuntimeOrigin::signed(account(1)),
+						transfer(hash, ISO8583Status::Approved)
+					));
+				}
+
+				// `MaxPrunedPerBlock` in a block, the rest in the next one
+				System::set_block_number(21);
+				ISO8583::on_initialize(21);
+				assert_eq!(SettledTransactions::<Test>::iter().count(), 1);
+
+				System::set_block_number(22);
+				ISO8583::on_initialize(22);
+				assert_eq!(SettledTransactions::<Test>::iter().count(), 0);
+			});
+	}
+}
+
+mod bonding {
+	use super::*;
+	use crate::OracleBonds;
+
+	#[test]
+	fn oracles_bond_on_registration() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				// genesis oracles are bonded
+				assert_eq!(Balances::reserved_balance(account(1)), OracleBond::get());
+				assert_eq!(OracleBonds::<Test>::get(account(1)).unwrap().unlocking_at, None);
+
+				assert_ok!(ISO8583::register_oracle(RuntimeOrigin::root(), account(3)));
+
+				System::assert_last_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::OracleBonded { oracle: account(3), amount: 10 },
+				));
+				assert_eq!(Balances::reserved_balance(account(3)), OracleBond::get());
+
+				// registering again does not bond twice
+				assert_ok!(ISO8583::register_oracle(RuntimeOrigin::root(), account(3)));
+				assert_eq!(Balances::reserved_balance(account(3)), OracleBond::get());
+
+				// oracle must be able to pay the bond
+				assert_noop!(
+					ISO8583::register_oracle(RuntimeOrigin::root(), account(6)),
+					pallet_balances::Error::<Test>::InsufficientBalance,
+				);
+			});
+	}
+
+	#[test]
+	fn unbonding_is_delayed() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				assert_noop!(
+					ISO8583::withdraw_bond(RuntimeOrigin::signed(account(3))),
+					Error::<Test>::NotBonded,
+				);
+				assert_noop!(
+					ISO8583::withdraw_bond(RuntimeOrigin::signed(account(1))),
+					Error::<Test>::NotUnbonding,
+				);
+
+				assert_ok!(ISO8583::remove_oracle(RuntimeOrigin::root(), account(1)));
+
+				System::assert_last_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::OracleUnbonding { oracle: account(1), unlocking_at: 6 },
+				));
+
+				assert_noop!(
+					ISO8583::withdraw_bond(RuntimeOrigin::signed(account(1))),
+					Error::<Test>::BondLocked,
+				);
+
+				System::set_block_number(6);
+
+				assert_ok!(ISO8583::withdraw_bond(RuntimeOrigin::signed(account(1))));
+
+				System::assert_last_event(RuntimeEvent::ISO8583(
+					crate::Event::<Test>::BondWithdrawn { oracle: account(1), amount: 10 },
+				));
+				assert_eq!(Balances::reserved_balance(account(1)), 0);
+				assert_eq!(Balances::free_balance(account(1)), INITIAL_BALANCE);
+				assert!(!OracleBonds::<Test>::contains_key(account(1)));
+			});
+	}
+
+	#[test]
+	fn admin_can_slash_bond() {
+		ExtBuilder::default().with_oracle_accounts(vec![1, 2]).build_and_execute(|| {
+			System::set_block_number(1);
+
+			assert_noop!(
+				ISO8583::slash_oracle(RuntimeOrigin::signed(account(2)), account(1), 4),
+				DispatchError::BadOrigin,
+			);
+			assert_noop!(
+				ISO8583::slash_oracle(RuntimeOrigin::root(), account(3), 4),
+				Error::<Test>::NotBonded,
+			);
+
+			assert_ok!(ISO8583::slash_oracle(RuntimeOrigin::root(), account(1), 4));
+
+			System::assert_last_event(RuntimeEvent::ISO8583(crate::Event::<Test>::DeductFunds {
+				who: account(1),
+				amount: 4,
+			}));
+			assert_eq!(OracleBonds::<Test>::get(account(1)).unwrap().amount, 6);
+			assert_eq!(Balances::reserved_balance(account(1)), 6);
+			assert_eq!(Balances::free_balance(account(1)), INITIAL_BALANCE - 10);
+
+			// at most the bond is slashed
+			assert_ok!(ISO8583::slash_oracle(RuntimeOrigin::root(), account(1), 100));
+			assert_eq!(OracleBonds::<Test>::get(account(1)).unwrap().amount, 0);
+			assert_eq!(Balances::free_balance(account(1)), INITIAL_BALANCE - 10);
+
+			// removed oracles can be slashed while unbonding
+			assert_ok!(ISO8583::remove_oracle(RuntimeOrigin::root(), account(2)));
+			assert_ok!(ISO8583::slash_oracle(RuntimeOrigin::root(), account(2), 10));
+			assert!(!OracleBonds::<Test>::contains_key(account(2)));
+		});
+	}
+
+	#[test]
+	fn re_registration_reuses_unbonding_bond() {
+		ExtBuilder::default().with_oracle_accounts(vec![1]).build_and_execute(|| {
+			System::set_block_number(1);
+
+			assert_ok!(ISO8583::remove_oracle(RuntimeOrigin::root(), account(1)));
+			assert_ok!(ISO8583::slash_oracle(RuntimeOrigin::root(), account(1), 3));
+
+			assert_ok!(ISO8583::register_oracle(RuntimeOrigin::root(), account(1)));
+
+			// slashed part is topped up
+			System::assert_last_event(RuntimeEvent::ISO8583(crate::Event::<Test>::OracleBonded {
+				oracle: account(1),
+				amount: 3,
+			}));
+			assert_eq!(OracleBonds::<Test>::get(account(1)), Some(crate::types::Bond::new(10)));
+			assert_eq!(Balances::reserved_balance(account(1)), OracleBond::get());
+		});
+	}
+
+	#[test]
+	fn settlements_do_not_release_bonds() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2])
+			.with_accounts(vec![3])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+
+				// bonded oracle with a pending transfer
+				assert_ok!(ISO8583::register(RuntimeOrigin::signed(account(2)), account(1), 0));
+				assert_ok!(ISO8583::initiate_transfer(
+					RuntimeOrigin::signed(account(1)),
+					account(1),
+					account(3),
+					5
+				));
+				assert_eq!(Balances::reserved_balance(account(1)), OracleBond::get() + 5);
+
+				// settles more than is pending, e.g. a revert of a transfer to the oracle
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(2)),
+					FinalisedTransaction {
+						from: account(1),
+						to: account(3),
+						amount: 20,
+						hash: H256::from([1; 32]),
+						event_id: (1_u32, 0_u32).encode().try_into().unwrap(),
+						status: crate::types::ISO8583Status::Approved,
+					}
+				));
+
+				assert_eq!(Balances::reserved_balance(account(1)), OracleBond::get());
+				assert_eq!(crate::PendingReserves::<Test>::get(account(1)), 0);
+				assert_eq!(Balances::free_balance(account(1)), INITIAL_BALANCE - 10 - 20);
+				assert_eq!(Balances::free_balance(account(3)), INITIAL_BALANCE + 20);
+			});
+	}
+}
+
+mod heartbeats {
+	use super::*;
+	use crate::{NetworkManagementCode, NetworkManagementMessage, OracleLiveness, OracleStatus};
+	use frame_support::traits::Hooks;
+
+	fn message(code: NetworkManagementCode) -> NetworkManagementMessage {
+		NetworkManagementMessage { code, stan: 42, response_code: *b"00" }
+	}
+
+	#[test]
+	fn heartbeat_updates_liveness() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3])
+			.build_and_execute(|| {
+				System::set_block_number(3);
+
+				// registered oracles start active
+				let liveness = OracleLiveness::<Test>::get(account(1)).unwrap();
+				assert_eq!(liveness.status, OracleStatus::Active);
+				assert_eq!(liveness.last_seen, 0);
+
+				assert_noop!(
+					ISO8583::heartbeat(RuntimeOrigin::signed(account(3)), None),
+					DispatchError::BadOrigin,
+				);
+
+				assert_ok!(ISO8583::heartbeat(
+					RuntimeOrigin::signed(account(1)),
+					Some(message(NetworkManagementCode::EchoTest))
+				));
+
+				System::assert_last_event(RuntimeEvent::ISO8583(crate::Event::<Test>::Heartbeat {
+					oracle: account(1),
+					message: Some(message(NetworkManagementCode::EchoTest)),
+				}));
+
+				let liveness = OracleLiveness::<Test>::get(account(1)).unwrap();
+				assert_eq!(liveness.last_seen, 3);
+				assert_eq!(liveness.last_message, Some(message(NetworkManagementCode::EchoTest)));
+
+				// signing off makes the oracle inactive
+				System::set_block_number(4);
+				assert_ok!(ISO8583::heartbeat(
+					RuntimeOrigin::signed(account(1)),
+					Some(message(NetworkManagementCode::SignOff))
+				));
+				assert_eq!(
+					ISO8583::oracle_health(account(1)).unwrap().status,
+					OracleStatus::SignedOff
+				);
+
+				// plain heartbeat keeps the last message
+				assert_ok!(ISO8583::heartbeat(RuntimeOrigin::signed(account(1)), None));
+				let liveness = OracleLiveness::<Test>::get(account(1)).unwrap();
+				assert_eq!(liveness.status, OracleStatus::Active);
+				assert_eq!(liveness.last_message, Some(message(NetworkManagementCode::SignOff)));
+
+				// removed oracles are not tracked
+				assert_ok!(ISO8583::remove_oracle(RuntimeOrigin::root(), account(1)));
+				assert_eq!(ISO8583::oracle_health(account(1)), None);
+			});
+	}
+
+	#[test]
+	fn stale_oracles_are_marked_inactive() {
+		ExtBuilder::default().with_oracle_accounts(vec![1, 2]).build_and_execute(|| {
+			System::set_block_number(5);
+			assert_ok!(ISO8583::heartbeat(RuntimeOrigin::signed(account(2)), None));
+
+			// checked only every `HeartbeatTimeout` blocks
+			ISO8583::on_initialize(9);
+			assert_eq!(ISO8583::oracle_health(account(1)).unwrap().status, OracleStatus::Active);
+
+			System::set_block_number(10);
+			ISO8583::on_initialize(10);
+
+			System::assert_has_event(RuntimeEvent::ISO8583(crate::Event::<Test>::OracleStale {
+				oracle: account(1),
+			}));
+
+			let mut health = ISO8583::oracles_health()
+				.into_iter()
+				.map(|(oracle, liveness)| (oracle, liveness.unwrap().status))
+				.collect::<Vec<_>>();
+			health.sort_by_key(|(oracle, _)| *oracle);
+			let mut expected =
+				vec![(account(1), OracleStatus::Stale), (account(2), OracleStatus::Active)];
+			expected.sort_by_key(|(oracle, _)| *oracle);
+			assert_eq!(health, expected);
+
+			// heartbeat brings the oracle back
+			assert_ok!(ISO8583::heartbeat(RuntimeOrigin::signed(account(1)), None));
+			assert_eq!(ISO8583::oracle_health(account(1)).unwrap().status, OracleStatus::Active);
+		});
+	}
+}
+
+mod statistics {
+	use super::*;
+	use crate::{
+		types::{ISO8583FailureReason, ISO8583Status},
+		SettlementStats, StatsWindow,
+	};
+	use frame_support::traits::Hooks;
+
+	fn transfer(
+		hash: u8,
+		initiated_at: u32,
+		status: ISO8583Status,
+	) -> crate::types::FinalisedTransactionOf<Test> {
+		FinalisedTransaction {
+			from: account(3),
+			to: account(4),
+			amount: 20,
+			hash: H256::from([hash; 32]),
+			event_id: (initiated_at, 0_u32).encode().try_into().unwrap(),
+			status,
+		}
+	}
+
+	#[test]
+	fn attestations_are_recorded() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(4);
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transfer(1, 1, ISO8583Status::Approved)
+				));
+
+				// settled later than `SettlementTimeout`
+				System::set_block_number(9);
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transfer(2, 1, ISO8583Status::Failed(ISO8583FailureReason::DoNotHonor))
+				));
+
+				let expected = SettlementStats {
+					approved: 1,
+					failed: 1,
+					timed_out: 1,
+					measured: 2,
+					total_latency: 11,
+					max_latency: 8,
+				};
+				let stats = ISO8583::oracle_stats(account(1));
+				assert_eq!(stats.total, expected);
+				assert_eq!(stats.total.average_latency(), Some(5));
+				assert_eq!(
+					stats.windows.into_inner(),
+					vec![StatsWindow { start: 0, stats: expected.clone() }]
+				);
+
+				// next window
+				System::set_block_number(12);
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transfer(3, 11, ISO8583Status::Approved)
+				));
+
+				let stats = ISO8583::oracle_stats(account(1));
+				assert_eq!(stats.total.approved, 2);
+				assert_eq!(stats.windows.len(), 2);
+				assert_eq!(stats.windows[1].start, 10);
+				assert_eq!(stats.windows[1].stats.average_latency(), Some(1));
+
+				// only oracles that attested are listed
+				assert_eq!(ISO8583::oracle_stats(account(2)), Default::default());
+				assert_eq!(
+					ISO8583::oracles_stats()
+						.into_iter()
+						.map(|(oracle, _)| oracle)
+						.collect::<Vec<_>>(),
+					vec![account(1)]
+				);
+			});
+	}
+
+	#[test]
+	fn missed_requests_are_timed_out() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1, 2, 5])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				System::set_block_number(1);
+				FinalityThreshold::set(2);
+
+				assert_ok!(ISO8583::submit_finality(
+					RuntimeOrigin::signed(account(1)),
+					transfer(1, 1, ISO8583Status::Approved)
+				));
+
+				// the transaction expires without a quorum
+				System::set_block_number(21);
+				ISO8583::on_initialize(21);
+
+				// oracles that never answered missed it
+				for oracle in [2, 5] {
+					let stats = ISO8583::oracle_stats(account(oracle));
+					assert_eq!(stats.total, SettlementStats { timed_out: 1, ..Default::default() });
+					assert_eq!(stats.windows[0].start, 20);
+				}
+
+				let stats = ISO8583::oracle_stats(account(1));
+				assert_eq!(stats.total.approved, 1);
+				assert_eq!(stats.total.timed_out, 0);
+			});
+	}
+
+	#[test]
+	fn windows_are_bounded() {
+		ExtBuilder::default()
+			.with_oracle_accounts(vec![1])
+			.with_accounts(vec![3, 4])
+			.build_and_execute(|| {
+				for (hash, block) in [5, 15, 25, 35].into_iter().enumerate() {
+					System::set_block_number(block);
+					assert_ok!(ISO8583::submit_finality(
+						RuntimeOrigin::signed(account(1)),
+						transfer(hash as u8, block as u32, ISO8583Status::Approved)
+					));
+				}
+
+				let stats = ISO8583::oracle_stats(account(1));
+				assert_eq!(stats.total.approved, 4);
+				assert_eq!(
+					stats.windows.iter().map(|window| window.start).collect::<Vec<_>>(),
+					vec![10, 20, 30]
+				);
+			});
+	}
 }
@@ -2,10 +2,17 @@
 
 use codec::{Decode, Encode, MaxEncodedLen};
 use frame_support::traits::Currency;
-use frame_system::offchain::{SignedPayload, SigningTypes};
+use frame_system::{
+	offchain::{SignedPayload, SigningTypes},
+	pallet_prelude::BlockNumberFor,
+};
 use scale_info::TypeInfo;
 use sp_core::{ConstU32, RuntimeDebug};
-use sp_runtime::BoundedVec;
+use sp_runtime::{
+	traits::{AtLeast32BitUnsigned, Saturating},
+	BoundedVec,
+};
+use sp_std::vec::Vec;
 
 use crate::{AccountsOf, Config, StorageKey};
 
@@ -24,6 +31,52 @@ pub type BalanceOf<T> = <<T as Config>::Currency as Currency<AccountIdOf<T>>>::B
 /// Explicit `Transaction`
 pub type FinalisedTransactionOf<T> = FinalisedTransaction<AccountIdOf<T>, BalanceOf<T>>;
 
+/// Explicit `AccountInfo`
+pub type AccountInfoOf<T> = AccountInfo<BlockNumberFor<T>>;
+
+/// Explicit `OracleInfo`
+pub type OracleInfoOf<T> = OracleInfo<BlockNumberFor<T>>;
+
+/// Explicit `DriftStats`
+pub type DriftStatsOf<T> = DriftStats<BalanceOf<T>>;
+
+/// Explicit `RefreshRequest`
+pub type RefreshRequestOf<T> = RefreshRequest<BlockNumberFor<T>>;
+
+/// Explicit `TransferRequest`
+pub type TransferRequestOf<T> = TransferRequest<AccountIdOf<T>, BalanceOf<T>>;
+
+/// Explicit `TransferInfo`
+pub type TransferInfoOf<T> = TransferInfo<AccountIdOf<T>>;
+
+/// Explicit `Bond`
+pub type BondOf<T> = Bond<BalanceOf<T>, BlockNumberFor<T>>;
+
+/// Explicit `Liveness`
+pub type LivenessOf<T> = Liveness<BlockNumberFor<T>>;
+
+/// Explicit `SyncState`
+pub type SyncStateOf<T> = SyncState<BlockNumberFor<T>>;
+
+/// Explicit `FailoverState`
+pub type FailoverStateOf<T> = FailoverState<BlockNumberFor<T>>;
+
+/// Explicit `OracleStats`
+pub type OracleStatsOf<T> = OracleStats<
+	BlockNumberFor<T>,
+	BoundedVec<StatsWindow<BlockNumberFor<T>>, <T as Config>::MaxStatsWindows>,
+>;
+
+/// Explicit `Attestation`
+pub type AttestationOf<T> =
+	Attestation<FinalisedTransactionOf<T>, BoundedVec<AccountIdOf<T>, <T as Config>::MaxOracles>>;
+
+/// Public key of the payment processor
+pub type ProcessorKey = sp_core::sr25519::Public;
+
+/// Signature of the payment processor
+pub type ProcessorSignature = sp_core::sr25519::Signature;
+
 /// Event ID: `block_number` - `event_index`
 pub type EventId = BoundedVec<u8, ConstU32<16>>;
 
@@ -47,6 +100,257 @@ pub struct FinalisedTransaction<AccountId, Balance> {
 	pub status: ISO8583Status,
 }
 
+/// Account registered in the oracle
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct AccountInfo<BlockNumber> {
+	/// Block at which the account was registered
+	pub registered_at: BlockNumber,
+	/// Block at which the balance was last synced with the offchain ledger
+	pub last_synced_at: Option<BlockNumber>,
+}
+
+impl<BlockNumber> AccountInfo<BlockNumber> {
+	/// New account, registered at `registered_at` and never synced
+	pub fn new(registered_at: BlockNumber) -> Self {
+		Self { registered_at, last_synced_at: None }
+	}
+}
+
+/// Cumulative corrections of on-chain balances to the offchain ledger
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, Default, MaxEncodedLen, TypeInfo)]
+pub struct DriftStats<Balance> {
+	/// Sum of corrections that increased balances
+	pub credited: Balance,
+	/// Sum of corrections that decreased balances
+	pub debited: Balance,
+	/// Number of corrected balances
+	pub corrections: u64,
+}
+
+/// Balance refresh requested by an account
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct RefreshRequest<BlockNumber> {
+	/// Block of the latest request
+	pub requested_at: BlockNumber,
+	/// Refreshed balance was not applied yet
+	pub pending: bool,
+}
+
+/// Request of an account holder, indexed offchain for the relay of the offchain worker
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo)]
+pub struct TransferRequest<AccountId, Balance> {
+	/// ID of the `InitiateTransfer` or `InitiateRevert` event of the request
+	pub event_id: EventId,
+	/// Requested transfer or revert
+	pub kind: RequestKind<AccountId, Balance>,
+}
+
+/// Kind of a request relayed to the payment processor
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo)]
+pub enum RequestKind<AccountId, Balance> {
+	/// Transfer of `amount` from `from` to `to`, relayed as a 0200 financial request
+	Transfer { from: AccountId, to: AccountId, amount: Balance },
+	/// Revert of the transaction `hash` by its sender `who`, relayed as a 0400 reversal request
+	Revert { hash: Hash, who: AccountId },
+}
+
+/// Transfer initiated on-chain
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct TransferInfo<AccountId> {
+	/// Sender of the transfer, the only account that can revert it
+	pub from: AccountId,
+	/// Transfer was settled as approved
+	pub approved: bool,
+}
+
+/// Oracle account registered in the pallet
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct OracleInfo<BlockNumber> {
+	/// Block at which the oracle was registered
+	pub registered_at: BlockNumber,
+}
+
+impl<BlockNumber> OracleInfo<BlockNumber> {
+	/// New oracle, registered at `registered_at`
+	pub fn new(registered_at: BlockNumber) -> Self {
+		Self { registered_at }
+	}
+}
+
+/// Funds bonded by an oracle account
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Bond<Balance, BlockNumber> {
+	/// Reserved amount, can be slashed while bonded or unbonding
+	pub amount: Balance,
+	/// Block from which the bond can be withdrawn, `None` while the oracle is registered
+	pub unlocking_at: Option<BlockNumber>,
+}
+
+impl<Balance, BlockNumber> Bond<Balance, BlockNumber> {
+	/// New bond of an active oracle
+	pub fn new(amount: Balance) -> Self {
+		Self { amount, unlocking_at: None }
+	}
+}
+
+/// Oracle attestations of a finalised transaction
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Attestation<Transaction, Oracles> {
+	/// Attested transaction
+	pub transaction: Transaction,
+	/// Oracles that attested the transaction
+	pub oracles: Oracles,
+}
+
+/// ISO-8583 network management information code, DE70 of 0800/0810 messages
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum NetworkManagementCode {
+	/// `001`, oracle signed on with the processor
+	SignOn,
+	/// `002`, oracle signed off from the processor
+	SignOff,
+	/// `301`, echo test
+	EchoTest,
+}
+
+impl NetworkManagementCode {
+	/// Numeric value of DE70
+	pub fn code(&self) -> u16 {
+		match self {
+			Self::SignOn => 1,
+			Self::SignOff => 2,
+			Self::EchoTest => 301,
+		}
+	}
+}
+
+/// 0800/0810 exchange between an oracle and the payment processor
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct NetworkManagementMessage {
+	/// Network management information code, DE70
+	pub code: NetworkManagementCode,
+	/// System trace audit number, DE11
+	pub stan: u32,
+	/// Response code of the 0810 response, DE39. `00` means approved
+	pub response_code: [u8; 2],
+}
+
+/// Liveness status of an oracle
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum OracleStatus {
+	/// Oracle sent a heartbeat within `HeartbeatTimeout`
+	Active,
+	/// Oracle signed off from the processor
+	SignedOff,
+	/// Oracle missed its heartbeats
+	Stale,
+}
+
+/// Liveness of an oracle, as reported by its heartbeats
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Liveness<BlockNumber> {
+	/// Block of the last heartbeat, or of the registration if there was none
+	pub last_seen: BlockNumber,
+	/// Current status
+	pub status: OracleStatus,
+	/// Last network management message exchanged with the processor
+	pub last_message: Option<NetworkManagementMessage>,
+}
+
+impl<BlockNumber> Liveness<BlockNumber> {
+	/// Active oracle, last seen at `last_seen`
+	pub fn new(last_seen: BlockNumber) -> Self {
+		Self { last_seen, status: OracleStatus::Active, last_message: None }
+	}
+}
+
+/// Settlement statistics of an oracle over a period of time
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct SettlementStats<BlockNumber> {
+	/// Approved transactions attested by the oracle
+	pub approved: u32,
+	/// Failed transactions attested by the oracle
+	pub failed: u32,
+	/// Attestations submitted later than `SettlementTimeout` after the transfer was initiated,
+	/// and requests that expired before the oracle attested them
+	pub timed_out: u32,
+	/// Attestations with a known latency
+	pub measured: u32,
+	/// Sum of latencies of measured attestations, in blocks
+	pub total_latency: BlockNumber,
+	/// Highest latency of measured attestations, in blocks
+	pub max_latency: BlockNumber,
+}
+
+impl<BlockNumber: AtLeast32BitUnsigned + Copy> SettlementStats<BlockNumber> {
+	/// Record an attestation with an optional latency
+	pub fn record(&mut self, approved: bool, latency: Option<BlockNumber>, timed_out: bool) {
+		if approved {
+			self.approved.saturating_inc();
+		} else {
+			self.failed.saturating_inc();
+		}
+
+		if timed_out {
+			self.timed_out.saturating_inc();
+		}
+
+		if let Some(latency) = latency {
+			self.measured.saturating_inc();
+			self.total_latency = self.total_latency.saturating_add(latency);
+			self.max_latency = self.max_latency.max(latency);
+		}
+	}
+
+	/// Record a request that expired before the oracle attested it
+	pub fn record_missed(&mut self) {
+		self.timed_out.saturating_inc();
+	}
+
+	/// Average latency of measured attestations, in blocks
+	pub fn average_latency(&self) -> Option<BlockNumber> {
+		(self.measured > 0).then(|| self.total_latency / self.measured.into())
+	}
+}
+
+/// Settlement statistics of an oracle in a window of `StatsWindowLength` blocks
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct StatsWindow<BlockNumber> {
+	/// First block of the window
+	pub start: BlockNumber,
+	/// Statistics of the window
+	pub stats: SettlementStats<BlockNumber>,
+}
+
+/// Settlement statistics of an oracle
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct OracleStats<BlockNumber, Windows> {
+	/// Statistics of all attestations of the oracle
+	pub total: SettlementStats<BlockNumber>,
+	/// Statistics of the most recent windows, oldest first
+	pub windows: Windows,
+}
+
+/// Balance the offchain ledger reports for an account, like the amount types of DE54
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum LedgerView {
+	/// Funds of pending transfers are already held by the ledger and excluded
+	Available,
+	/// Funds of pending transfers are still included
+	Ledger,
+}
+
+/// How the offchain worker submits balance updates
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum SubmissionMode {
+	/// Unsigned transactions, only included by the block producing node
+	Unsigned,
+	/// Transactions signed by the oracle account, which pays their fees
+	Signed,
+	/// Transactions signed by the oracle account, free of fees
+	SignedFeeless,
+}
+
 /// ISO-8583 transaction status
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
 pub enum ISO8583Status {
@@ -76,19 +380,51 @@ pub enum ISO8583FailureReason {
 /// Payload used by this example crate to hold price
 /// data required to submit a transaction.
 #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, scale_info::TypeInfo)]
-pub struct UpdateAccountsPayload<Public, Accounts, StorageKey> {
+pub struct UpdateAccountsPayload<Public, Accounts, StorageKey, BlockNumber> {
 	/// Public key of the off-chain worker
 	pub public: Public,
 	/// Updated accounts
 	pub accounts: Accounts,
-	/// Last iterated storage key
-	pub last_key: StorageKey,
+	/// Last iterated storage key, `None` for changed balances which leave the scan of `Accounts`
+	/// where it is
+	pub last_key: Option<StorageKey>,
+	/// Block in which the balances were fetched
+	pub at: BlockNumber,
+	/// Signature of the payment processor over `(accounts, at)`
+	pub processor_signature: Option<ProcessorSignature>,
+	/// Index of the endpoint in `ProcessorEndpoints` that served the balances, `None` for the
+	/// url local to the node
+	pub endpoint: Option<u32>,
 }
 
 impl<T: SigningTypes + crate::Config> SignedPayload<T>
-	for UpdateAccountsPayload<T::Public, AccountsOf<T>, StorageKey>
+	for UpdateAccountsPayload<T::Public, AccountsOf<T>, StorageKey, BlockNumberFor<T>>
 {
 	fn public(&self) -> T::Public {
 		self.public.clone()
 	}
 }
+
+/// Balance sync state of the offchain worker, kept in offchain local storage
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, Default)]
+pub struct SyncState<BlockNumber> {
+	/// Consecutive failed runs
+	pub failures: u32,
+	/// Block from which a failed run is retried
+	pub retry_at: Option<BlockNumber>,
+	/// Syncing is paused until this block after too many failed runs
+	pub paused_until: Option<BlockNumber>,
+}
+
+/// Failover state of the payment processor endpoints, kept in offchain local storage
+///
+/// Endpoints are indexed in the order the node tries them, see `Pallet::endpoints`.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, Default)]
+pub struct FailoverState<BlockNumber> {
+	/// Index of the endpoint in use, `0` is the primary
+	pub active: u32,
+	/// Consecutive failures of each endpoint
+	pub failures: Vec<u32>,
+	/// Block in which the primary was last probed while failed over
+	pub probed_at: Option<BlockNumber>,
+}
@@ -101,10 +101,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("iso-8583"),
 	impl_name: create_runtime_str!("iso-8583"),
 	authoring_version: 1,
-	spec_version: 100,
+	spec_version: 104,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 3,
 	state_version: 1,
 };
 
@@ -262,10 +262,71 @@ impl pallet_transaction_payment::Config for Runtime {
 	type FeeMultiplierUpdate = ConstFeeMultiplier<FeeMultiplier>;
 }
 
-impl pallet_sudo::Config for Runtime {
+parameter_types! {
+	pub const CouncilMotionDuration: BlockNumber = DAYS;
+	pub const CouncilMaxProposals: u32 = 100;
+	pub const CouncilMaxMembers: u32 = 100;
+	pub MaxCouncilProposalWeight: Weight = Perbill::from_percent(50) * BlockWeights::get().max_block;
+}
+
+/// Council of the consortium, owns the oracle set and the payment processor configuration.
+pub type CouncilCollective = pallet_collective::Instance1;
+
+/// More than half of the council.
+pub type EnsureCouncilMajority =
+	pallet_collective::EnsureProportionMoreThan<AccountId, CouncilCollective, 1, 2>;
+
+impl pallet_collective::Config<CouncilCollective> for Runtime {
+	type RuntimeOrigin = RuntimeOrigin;
+	type Proposal = RuntimeCall;
+	type RuntimeEvent = RuntimeEvent;
+	type MotionDuration = CouncilMotionDuration;
+	type MaxProposals = CouncilMaxProposals;
+	type MaxMembers = CouncilMaxMembers;
+	type DefaultVote = pallet_collective::PrimeDefaultVote;
+	type WeightInfo = pallet_collective::weights::SubstrateWeight<Runtime>;
+	/// Members are managed by `CouncilMembership`.
+	type SetMembersOrigin = frame_system::EnsureNever<()>;
+	type MaxProposalWeight = MaxCouncilProposalWeight;
+}
+
+impl pallet_membership::Config<pallet_membership::Instance1> for Runtime {
+	type RuntimeEvent = RuntimeEvent;
+	type AddOrigin = EnsureCouncilMajority;
+	type RemoveOrigin = EnsureCouncilMajority;
+	type SwapOrigin = EnsureCouncilMajority;
+	type ResetOrigin = EnsureCouncilMajority;
+	type PrimeOrigin = EnsureCouncilMajority;
+	type MembershipInitialized = Council;
+	type MembershipChanged = Council;
+	type MaxMembers = CouncilMaxMembers;
+	type WeightInfo = pallet_membership::weights::SubstrateWeight<Runtime>;
+}
+
+parameter_types! {
+	/// Deposit of a noted preimage, 10 units
+	pub const PreimageBaseDeposit: Balance = 10_000_000;
+	/// Deposit per byte of a noted preimage
+	pub const PreimageByteDeposit: Balance = 1_000;
+}
+
+impl pallet_preimage::Config for Runtime {
+	type RuntimeEvent = RuntimeEvent;
+	type WeightInfo = pallet_preimage::weights::SubstrateWeight<Runtime>;
+	type Currency = Balances;
+	type ManagerOrigin = EnsureCouncilMajority;
+	type BaseDeposit = PreimageBaseDeposit;
+	type ByteDeposit = PreimageByteDeposit;
+}
+
+/// Root calls, such as runtime upgrades, are whitelisted and dispatched by the council.
+impl pallet_whitelist::Config for Runtime {
 	type RuntimeEvent = RuntimeEvent;
 	type RuntimeCall = RuntimeCall;
-	type WeightInfo = pallet_sudo::weights::SubstrateWeight<Runtime>;
+	type WhitelistOrigin = EnsureCouncilMajority;
+	type DispatchWhitelistedOrigin = EnsureCouncilMajority;
+	type Preimages = Preimage;
+	type WeightInfo = pallet_whitelist::weights::SubstrateWeight<Runtime>;
 }
 
 impl frame_system::offchain::SigningTypes for Runtime {
@@ -329,15 +390,57 @@ where
 parameter_types! {
 	/// Pallet account ID
 	pub PalletAccount: AccountId = PalletId(*b"py/iso85").into_account_truncating();
+	/// Bond of an oracle, 100 units
+	pub const OracleBond: Balance = 100_000_000;
+	/// Balance correction reported with `DriftAlert`, 1000 units
+	pub const DriftAlertThreshold: Balance = 1_000_000_000;
+	/// Processor reports available balances, pending transfers are already held
+	pub const BalanceView: pallet_iso_8583::LedgerView = pallet_iso_8583::LedgerView::Available;
+	/// Validators include balance updates as unsigned transactions
+	pub const BalanceUpdateMode: pallet_iso_8583::SubmissionMode =
+		pallet_iso_8583::SubmissionMode::Unsigned;
 }
 
 impl pallet_iso_8583::Config for Runtime {
 	type RuntimeEvent = RuntimeEvent;
+	/// Balances are queried from the payment processor `/balances` JSON endpoint. Use
+	/// `pallet_iso_8583::ledger::Iso8583Ledger` for processors answering ISO-8583 balance
+	/// inquiries.
+	type LedgerSource = pallet_iso_8583::ledger::HttpJsonLedger<Runtime>;
+	type TransferRelay = ();
 	type Currency = Balances;
 	type AuthorityId = pallet_iso_8583::crypto::Iso8583AuthId;
 	type PalletAccount = PalletAccount;
 	type MaxStringSize = ConstU32<1024>;
 	type OffchainWorkerInterval = ConstU32<20>;
+	type MaxBatchSize = ConstU32<20>;
+	type ReconciliationInterval = ConstU32<{ 20 * MINUTES }>;
+	type MaxDirtyAccounts = ConstU32<256>;
+	type DirtyAccountTimeout = ConstU32<{ 10 * MINUTES }>;
+	type RefreshCooldown = ConstU32<{ 10 * MINUTES }>;
+	type FeelessRefresh = ConstBool<true>;
+	type DriftAlertThreshold = DriftAlertThreshold;
+	type BalanceView = BalanceView;
+	type BalanceUpdateMode = BalanceUpdateMode;
+	type OffchainRequestDeadline = ConstU64<2_000>;
+	type MaxSyncRetries = ConstU32<5>;
+	type SyncPauseDuration = ConstU32<{ 10 * MINUTES }>;
+	type MaxEndpoints = ConstU32<4>;
+	type EndpointProbeInterval = ConstU32<{ 10 * MINUTES }>;
+	/// A single oracle settles transactions on the development chain, raise along with the number
+	/// of independent oracle operators.
+	type FinalityThreshold = ConstU32<1>;
+	type MaxOracles = ConstU32<16>;
+	type OracleBond = OracleBond;
+	type UnbondingPeriod = ConstU32<{ 7 * DAYS }>;
+	type HeartbeatTimeout = ConstU32<{ 10 * MINUTES }>;
+	type SettlementTimeout = ConstU32<{ 5 * MINUTES }>;
+	type RetentionPeriod = ConstU32<{ 7 * DAYS }>;
+	type MaxPrunedPerBlock = ConstU32<100>;
+	type StatsWindowLength = ConstU32<HOURS>;
+	type MaxStatsWindows = ConstU32<24>;
+	type AdminOrigin = EnsureCouncilMajority;
+	type OracleOrigin = pallet_iso_8583::EnsureOracle<Runtime>;
 }
 
 // Create the runtime by composing the FRAME pallets that were previously configured.
@@ -350,10 +453,17 @@ construct_runtime!(
 		// We don't allow any extrinsics for balances pallet.
 		Balances: pallet_balances::{Pallet, Storage, Config<T>, Event<T>},
 		TransactionPayment: pallet_transaction_payment,
-		Sudo: pallet_sudo,
 
-		// ISO-8583 pallet
-		ISO8583: pallet_iso_8583
+		// ISO-8583 pallet, index 6 belonged to the removed `Sudo` pallet
+		ISO8583: pallet_iso_8583 = 7,
+
+		// Governance of the oracle set
+		Council: pallet_collective::<Instance1>,
+		CouncilMembership: pallet_membership::<Instance1>,
+
+		// Root calls approved by the council
+		Preimage: pallet_preimage,
+		Whitelist: pallet_whitelist,
 	}
 );
 
@@ -380,6 +490,19 @@ pub type UncheckedExtrinsic =
 	generic::UncheckedExtrinsic<Address, RuntimeCall, Signature, SignedExtra>;
 /// The payload being signed in transactions.
 pub type SignedPayload = generic::SignedPayload<RuntimeCall, SignedExtra>;
+
+parameter_types! {
+	/// Storage prefix of the removed `Sudo` pallet
+	pub const SudoPalletName: &'static str = "Sudo";
+}
+
+/// Migrations to apply on runtime upgrade.
+pub type Migrations = (
+	pallet_iso_8583::migrations::v1::MigrateToV1<Runtime>,
+	pallet_iso_8583::migrations::v2::MigrateToV2<Runtime>,
+	pallet_iso_8583::migrations::v3::MigrateToV3<Runtime>,
+	frame_support::migrations::RemovePallet<SudoPalletName, RocksDbWeight>,
+);
 /// Executive: handles dispatch to the various modules.
 pub type Executive = frame_executive::Executive<
 	Runtime,
@@ -387,6 +510,7 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPalletsWithSystem,
+	Migrations,
 >;
 
 #[cfg(feature = "runtime-benchmarks")]
@@ -400,7 +524,10 @@ mod benches {
 		[frame_system, SystemBench::<Runtime>]
 		[pallet_balances, Balances]
 		[pallet_timestamp, Timestamp]
-		[pallet_sudo, Sudo]
+		[pallet_collective, Council]
+		[pallet_membership, CouncilMembership]
+		[pallet_preimage, Preimage]
+		[pallet_whitelist, Whitelist]
 	);
 }
 
@@ -528,6 +655,33 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl pallet_iso_8583_runtime_api::ISO8583Api<
+		Block,
+		AccountId,
+		pallet_iso_8583::LivenessOf<Runtime>,
+		pallet_iso_8583::OracleStatsOf<Runtime>,
+	> for Runtime {
+		fn oracle_health(oracle: AccountId) -> Option<pallet_iso_8583::LivenessOf<Runtime>> {
+			ISO8583::oracle_health(oracle)
+		}
+
+		fn oracles_health() -> Vec<(AccountId, Option<pallet_iso_8583::LivenessOf<Runtime>>)> {
+			ISO8583::oracles_health()
+		}
+
+		fn oracle_stats(oracle: AccountId) -> pallet_iso_8583::OracleStatsOf<Runtime> {
+			ISO8583::oracle_stats(oracle)
+		}
+
+		fn oracles_stats() -> Vec<(AccountId, pallet_iso_8583::OracleStatsOf<Runtime>)> {
+			ISO8583::oracles_stats()
+		}
+
+		fn processor_endpoints() -> Vec<(Vec<u8>, bool)> {
+			ISO8583::processor_endpoints_status()
+		}
+	}
+
 	impl pallet_transaction_payment_rpc_runtime_api::TransactionPaymentApi<Block, Balance> for Runtime {
 		fn query_info(
 			uxt: <Block as BlockT>::Extrinsic,